@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use walkdir::WalkDir;
+
+use crate::fs::replace_file;
+
+/// Asset paths relative to `assets/`, e.g. `css/global.css`, mapped to the
+/// fingerprinted file they were renamed to.
+pub type AssetManifest = BTreeMap<String, String>;
+
+/// Number of hex digits of the content hash kept in fingerprinted names.
+const HASH_LEN: usize = 6;
+
+/// Elements whose contents are copied verbatim by `minify_html`.
+const RAW_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+static COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<!--(.*?)-->").unwrap());
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+
+/// Minifies and fingerprints the CSS and JS files copied to `dest_dir`.
+/// Returns the renamed files, empty unless `fingerprint` is set.
+pub fn process_assets(dest_dir: &Path, minify: bool, fingerprint: bool) -> Result<AssetManifest> {
+    let mut manifest = AssetManifest::new();
+    if !dest_dir.exists() || !(minify || fingerprint) {
+        return Ok(manifest);
+    }
+
+    // Collect the files before renaming any, so the walk never sees a
+    // fingerprinted file and hashes it again.
+    let files: Vec<_> = WalkDir::new(dest_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file())
+        .collect();
+
+    for path in &files {
+        let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+        if !matches!(ext, "css" | "js") {
+            continue;
+        }
+
+        let relative = path
+            .strip_prefix(dest_dir)
+            .context("failed to strip prefix")?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let mut content =
+            fs::read_to_string(path).with_context(|| format!("failed to read asset {path:?}"))?;
+
+        if minify {
+            let minified = match ext {
+                "css" => minifier::css::minify(&content).map(|m| m.to_string()),
+                _ => minifier::js::minify(&content).map(|m| m.to_string()),
+            };
+            match minified {
+                Ok(minified) => {
+                    content = minified;
+                    replace_file(path, &content)?;
+                }
+                Err(e) => eprintln!("warning: could not minify assets/{relative}: {e}"),
+            }
+        }
+
+        if fingerprint {
+            let hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let hashed = path.with_file_name(format!("{stem}.{}.{ext}", &hash[..HASH_LEN]));
+            fs::rename(path, &hashed)
+                .with_context(|| format!("failed to rename asset {path:?}"))?;
+
+            let hashed_relative = match relative.rsplit_once('/') {
+                Some((dir, _)) => {
+                    format!("{dir}/{}", hashed.file_name().unwrap().to_string_lossy())
+                }
+                None => hashed.file_name().unwrap().to_string_lossy().into_owned(),
+            };
+            manifest.insert(relative, hashed_relative);
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Writes the manifest as `assets-manifest.json` in the output directory.
+pub fn write_manifest(dist_dir: &Path, manifest: &AssetManifest) -> Result<()> {
+    let path = dist_dir.join("assets-manifest.json");
+    let json = serde_json::to_string_pretty(manifest)?;
+    fs::write(&path, json).with_context(|| format!("failed to write {path:?}"))
+}
+
+/// Registers `asset_url(path=...)`, which resolves an asset to its URL,
+/// following the manifest for fingerprinted files.
+pub fn register_function(tera: &mut tera::Tera, manifest: Arc<AssetManifest>) {
+    tera.register_function(
+        "asset_url",
+        move |args: &HashMap<String, tera::Value>| -> tera::Result<tera::Value> {
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| tera::Error::msg("asset_url() requires a `path` argument"))?;
+            let path = path.trim_start_matches('/');
+            let path = path.strip_prefix("assets/").unwrap_or(path);
+            let resolved = manifest.get(path).map(String::as_str).unwrap_or(path);
+            Ok(tera::Value::String(format!("/assets/{resolved}")))
+        },
+    );
+}
+
+/// Drops comments and collapses whitespace, leaving `<pre>`, `<textarea>`,
+/// `<script>` and `<style>` contents untouched. Conditional comments are kept.
+pub fn minify_html(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let next_raw = RAW_ELEMENTS
+            .iter()
+            .filter_map(|tag| find_open_tag(&lower, pos, tag).map(|start| (start, *tag)))
+            .min_by_key(|(start, _)| *start);
+
+        let Some((start, tag)) = next_raw else {
+            out.push_str(&collapse(&html[pos..]));
+            break;
+        };
+
+        out.push_str(&collapse(&html[pos..start]));
+        let close = format!("</{tag}");
+        let end = lower[start..]
+            .find(&close)
+            .map(|i| start + i)
+            .unwrap_or(html.len());
+        out.push_str(&html[start..end]);
+        pos = end;
+    }
+
+    out.trim().to_string()
+}
+
+fn find_open_tag(lower: &str, from: usize, tag: &str) -> Option<usize> {
+    let needle = format!("<{tag}");
+    let mut search = from;
+    while let Some(i) = lower[search..].find(&needle) {
+        let start = search + i;
+        let after = lower[start + needle.len()..].chars().next();
+        if matches!(after, Some('>' | '/') | Some(' ' | '\t' | '\n' | '\r')) {
+            return Some(start);
+        }
+        search = start + needle.len();
+    }
+    None
+}
+
+fn collapse(html: &str) -> String {
+    let html = COMMENT.replace_all(html, |caps: &regex::Captures| {
+        if caps[1].trim_start().starts_with('[') {
+            caps[0].to_string()
+        } else {
+            String::new()
+        }
+    });
+    WHITESPACE.replace_all(&html, " ").into_owned()
+}
@@ -0,0 +1,291 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use walkdir::WalkDir;
+
+/// External links requested at the same time.
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\s(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+static ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\s(?:id|name)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+static ENTITY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|amp|lt|gt|quot|apos);").unwrap());
+
+/// A link or resource reference in the output that does not resolve.
+pub struct Problem {
+    /// HTML file relative to the output directory.
+    pub file: PathBuf,
+    pub line: usize,
+    pub target: String,
+    pub reason: String,
+}
+
+struct Reference {
+    file: PathBuf,
+    line: usize,
+    target: String,
+}
+
+/// Checks every `href` and `src` in the HTML files of `dist_dir`. Internal targets
+/// must resolve to a file the dev server would serve, including extensionless
+/// routes and fragment ids; external ones are only requested when asked for.
+pub async fn check_output(dist_dir: &Path, external: bool) -> Result<Vec<Problem>> {
+    let mut references = Vec::new();
+    for entry in WalkDir::new(dist_dir) {
+        let entry = entry.context("failed to walk output directory")?;
+        let path = entry.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != "html") {
+            continue;
+        }
+
+        let html =
+            fs::read_to_string(path).with_context(|| format!("failed to read file {path:?}"))?;
+        for captures in ATTRIBUTE.captures_iter(&html) {
+            let value = captures.get(1).or_else(|| captures.get(2)).unwrap();
+            references.push(Reference {
+                file: path.to_path_buf(),
+                line: html[..value.start()].matches('\n').count() + 1,
+                target: decode_entities(value.as_str()),
+            });
+        }
+    }
+
+    let mut problems = Vec::new();
+    let mut ids = HashMap::new();
+    let mut external_refs = Vec::new();
+
+    for reference in references {
+        let target = reference.target.trim();
+        if target.is_empty() || target == "#" {
+            continue;
+        }
+
+        if is_external(target) {
+            if external {
+                external_refs.push(reference);
+            }
+            continue;
+        }
+        if has_scheme(target) {
+            continue;
+        }
+
+        if let Some(reason) = check_internal(dist_dir, &reference.file, target, &mut ids) {
+            problems.push(problem(dist_dir, &reference, reason));
+        }
+    }
+
+    if !external_refs.is_empty() {
+        problems.extend(check_external(dist_dir, external_refs).await?);
+    }
+
+    problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    Ok(problems)
+}
+
+fn problem(dist_dir: &Path, reference: &Reference, reason: String) -> Problem {
+    Problem {
+        file: reference
+            .file
+            .strip_prefix(dist_dir)
+            .unwrap_or(&reference.file)
+            .to_path_buf(),
+        line: reference.line,
+        target: reference.target.clone(),
+        reason,
+    }
+}
+
+fn check_internal(
+    dist_dir: &Path,
+    file: &Path,
+    target: &str,
+    ids: &mut HashMap<PathBuf, HashSet<String>>,
+) -> Option<String> {
+    let target = target.split('?').next().unwrap_or_default();
+    let (path, fragment) = match target.split_once('#') {
+        Some((path, fragment)) => (path, fragment),
+        None => (target, ""),
+    };
+
+    let resolved = if path.is_empty() {
+        file.to_path_buf()
+    } else {
+        let path = percent_decode(path);
+        let candidate = match path.strip_prefix('/') {
+            Some(rooted) => dist_dir.join(rooted),
+            None => file.parent().unwrap_or(dist_dir).join(&path),
+        };
+        match served_file(&candidate, &path) {
+            Some(resolved) => resolved,
+            None => return Some("target does not exist".to_string()),
+        }
+    };
+
+    if fragment.is_empty() || resolved.extension().is_none_or(|ext| ext != "html") {
+        return None;
+    }
+
+    let file_ids = ids
+        .entry(resolved.clone())
+        .or_insert_with(|| collect_ids(&resolved));
+    if file_ids.contains(&percent_decode(fragment)) {
+        None
+    } else {
+        Some(format!("no element with id `{fragment}`"))
+    }
+}
+
+/// The file the dev server responds with for a request path, if any.
+fn served_file(candidate: &Path, request: &str) -> Option<PathBuf> {
+    if candidate.is_file() {
+        return Some(candidate.to_path_buf());
+    }
+
+    let index = candidate.join("index.html");
+    if candidate.is_dir() && index.is_file() {
+        return Some(index);
+    }
+
+    let name = Path::new(request.trim_end_matches('/')).file_name()?;
+    if !name.to_string_lossy().contains('.') {
+        let html = candidate.with_file_name(format!("{}.html", name.to_string_lossy()));
+        if html.is_file() {
+            return Some(html);
+        }
+    }
+
+    None
+}
+
+fn collect_ids(file: &Path) -> HashSet<String> {
+    let html = fs::read_to_string(file).unwrap_or_default();
+    ID.captures_iter(&html)
+        .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| decode_entities(m.as_str()))
+        .collect()
+}
+
+async fn check_external(dist_dir: &Path, references: Vec<Reference>) -> Result<Vec<Problem>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .user_agent(concat!("arrow/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    let mut urls: Vec<String> = references.iter().map(|r| r.target.clone()).collect();
+    urls.sort();
+    urls.dedup();
+
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+    let mut tasks = tokio::task::JoinSet::new();
+    for url in urls {
+        let client = client.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire().await;
+            let result = request_status(&client, &url).await;
+            (url, result)
+        });
+    }
+
+    let mut failures = HashMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (url, result) = joined?;
+        if let Err(reason) = result {
+            failures.insert(url, reason);
+        }
+    }
+
+    Ok(references
+        .iter()
+        .filter_map(|reference| {
+            let reason = failures.get(&reference.target)?;
+            Some(problem(dist_dir, reference, reason.clone()))
+        })
+        .collect())
+}
+
+async fn request_status(client: &reqwest::Client, url: &str) -> Result<(), String> {
+    let url = if url.starts_with("//") {
+        format!("https:{url}")
+    } else {
+        url.to_string()
+    };
+
+    let mut response = client.head(&url).send().await;
+    if let Ok(r) = &response
+        && r.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED
+    {
+        response = client.get(&url).send().await;
+    }
+
+    match response {
+        Ok(r) if r.status().is_success() => Ok(()),
+        Ok(r) => Err(format!("responded with {}", r.status())),
+        Err(e) => Err(format!("request failed: {e}")),
+    }
+}
+
+fn is_external(target: &str) -> bool {
+    let lower = target.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")
+}
+
+/// `mailto:`, `data:`, `javascript:` and friends are never checked.
+fn has_scheme(target: &str) -> bool {
+    match target.find(':') {
+        Some(index) => target[..index]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')),
+        None => false,
+    }
+}
+
+fn decode_entities(value: &str) -> String {
+    ENTITY
+        .replace_all(value, |captures: &regex::Captures| {
+            let name = &captures[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ if name.starts_with("#x") || name.starts_with("#X") => {
+                    u32::from_str_radix(&name[2..], 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                }
+                _ => name[1..].parse().ok().and_then(char::from_u32),
+            };
+            decoded.map_or_else(|| captures[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
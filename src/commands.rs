@@ -1,11 +1,29 @@
 use anyhow::{Context, Result};
 use rayon::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
+use tera::Context as TeraContext;
 
-use crate::config::Config;
+use crate::assets;
+use crate::check;
+use crate::config::{self, Config, SiteConfig, Workspace};
+use crate::data;
 use crate::djot;
 use crate::fs;
+use crate::images::{self, ImageSet};
+use crate::lint;
+use crate::site::{BuildInfo, Site};
+use crate::templates;
+
+/// Template directories in override order: the theme's, then the workspace's.
+fn template_dirs(templates_dir: &Path, theme_dir: Option<&Path>) -> Vec<PathBuf> {
+    let theme = theme_dir.map(|dir| dir.join("templates"));
+    theme
+        .into_iter()
+        .chain([templates_dir.to_path_buf()])
+        .collect()
+}
 
 fn resolve_entry_path(entry: &str) -> Result<PathBuf> {
     let entry_path = PathBuf::from(entry);
@@ -17,40 +35,432 @@ fn resolve_entry_path(entry: &str) -> Result<PathBuf> {
     }
 }
 
-pub async fn build_command(entry: &str) -> Result<()> {
+/// Builds the workspace into its output directory, or into `out` if given.
+/// With `clean` unset, the output directory is built over instead of cleared.
+pub async fn build_command(
+    entry: &str,
+    check: bool,
+    env: &str,
+    out: Option<&Path>,
+    clean: bool,
+) -> Result<()> {
     let build_path = resolve_entry_path(entry)?;
+    let site_config = SiteConfig::load_env(&build_path, Some(env))?;
+    let dirs = site_config.dirs(&build_path);
 
-    let src_dir = build_path.join("src");
+    let src_dir = dirs.source;
 
     if !src_dir.exists() {
         anyhow::bail!(
-            "error: missing `src` directory. specify a valid project with the --entry flag."
+            "error: missing `{}` directory. specify a valid project with the --entry flag.",
+            src_dir
+                .strip_prefix(&build_path)
+                .unwrap_or(&src_dir)
+                .display()
         );
     }
 
-    let dist_dir = build_path.join("dist");
-    let assets_dir = src_dir.join("assets");
+    let dist_dir = out.map_or(dirs.output, Path::to_path_buf);
+    let assets_dir = dirs.assets;
+    let theme_dir = site_config.theme_dir(&build_path)?;
 
-    println!("starting build...");
+    println!("starting {env} build...");
     println!("source: {src_dir:?}");
     println!("destination: {dist_dir:?}");
 
-    fs::prepare_directories(&dist_dir).context("error preparing directories")?;
+    if clean {
+        fs::prepare_directories(&dist_dir, &build_path, &src_dir)
+            .context("error preparing directories")?;
+    } else {
+        std::fs::create_dir_all(&dist_dir).context("failed to create destination directory")?;
+    }
+
+    let walker = fs::Walker::new(&build_path, &site_config)?;
+
+    fs::copy_static(
+        &build_path.join("static"),
+        &dist_dir,
+        &walker,
+        site_config.copy,
+    )
+    .context("error copying static files")?;
+
+    // Workspace assets are copied last so they replace theme files of the same path.
+    let asset_dirs = theme_dir.iter().map(|dir| dir.join("assets"));
+    for dir in asset_dirs.chain([assets_dir.clone()]) {
+        fs::copy_assets(&dir, &dist_dir.join("assets"), &walker, site_config.copy)
+            .with_context(|| format!("error copying assets from {dir:?}"))?;
+    }
 
-    if let Err(e) = fs::copy_assets(&assets_dir, &dist_dir.join("assets")) {
-        eprintln!("warning: error copying assets: {e}");
+    let images = match &site_config.images {
+        Some(image_config) => images::process_images(
+            image_config,
+            &assets_dir,
+            &dist_dir.join("assets"),
+            &images::cache_dir(&build_path),
+            &walker,
+        )
+        .context("error processing images")?,
+        None => ImageSet::new(),
+    };
+    let images = Arc::new(images);
+
+    let manifest = assets::process_assets(
+        &dist_dir.join("assets"),
+        site_config.minify,
+        site_config.fingerprint,
+    )
+    .context("error processing assets")?;
+    if site_config.fingerprint {
+        assets::write_manifest(&dist_dir, &manifest).context("error writing asset manifest")?;
     }
 
-    let files = fs::collect_djot_files(&src_dir).context("error collecting Djot files")?;
+    let mut tera = templates::load(&template_dirs(&dirs.templates, theme_dir.as_deref()))
+        .context("error loading templates")?;
+    images::register_function(&mut tera, images.clone());
+    assets::register_function(&mut tera, Arc::new(manifest));
+    let files = fs::collect_djot_files(&src_dir, &walker).context("error collecting Djot files")?;
 
-    files.par_iter().for_each(|file| {
-        if let Err(e) = djot::process_djot_file(file, &src_dir, &dist_dir) {
-            eprintln!("error processing file {file:?}: {e}");
-        }
-    });
+    for issue in lint_files(&src_dir, &files, &site_config)? {
+        eprintln!("warning: {issue}");
+    }
+
+    let data = data::load(&build_path.join("data"), &walker).context("error loading data files")?;
+
+    let mut context = TeraContext::new();
+    context.insert("build", &BuildInfo::new(env));
+    context.insert("data", &data);
+
+    let site = Site::new(
+        src_dir,
+        dist_dir.clone(),
+        site_config,
+        tera,
+        images,
+        context,
+        &files,
+    );
+
+    fs::copy_page_files(
+        &site.src_dir,
+        &assets_dir,
+        &dist_dir,
+        &walker,
+        site.config.copy,
+        &site.page_dirs(),
+    )
+    .context("error copying page files")?;
+
+    if site.config.graph {
+        site.write_graph().context("error writing link graph")?;
+    }
+
+    let errors: Vec<(&PathBuf, anyhow::Error)> = site
+        .sources
+        .par_iter()
+        .filter_map(|source| {
+            djot::process_djot_file(source, &site)
+                .err()
+                .map(|e| (&source.path, e))
+        })
+        .collect();
+    let messages: Vec<(&PathBuf, String)> = errors
+        .iter()
+        .map(|(path, e)| (*path, format!("{e:#}")))
+        .collect();
+    report_page_errors(&messages);
+
+    let broken = errors
+        .iter()
+        .filter(|(_, e)| e.chain().any(|e| e.is::<djot::BrokenLinkError>()))
+        .count();
+    if broken > 0 {
+        anyhow::bail!("build failed: {broken} page(s) have broken links");
+    }
 
     println!("build completed!");
 
+    if check {
+        check_output(&dist_dir, false).await?;
+    }
+
+    Ok(())
+}
+
+/// Prints page errors, once per message, so a broken layout is reported once
+/// rather than for every page.
+fn report_page_errors(errors: &[(&PathBuf, String)]) {
+    let mut by_message: Vec<(&str, Vec<&PathBuf>)> = Vec::new();
+    for (path, message) in errors {
+        match by_message.iter_mut().find(|(m, _)| m == message) {
+            Some((_, paths)) => paths.push(path),
+            None => by_message.push((message, vec![path])),
+        }
+    }
+
+    for (message, paths) in by_message {
+        match paths.as_slice() {
+            [path] => eprintln!("error processing file {path:?}: {message}"),
+            paths => eprintln!("error processing {} files: {message}", paths.len()),
+        }
+    }
+}
+
+/// Files written by `arrow init`, relative to the workspace.
+const SCAFFOLD: [(&str, &str); 3] = [
+    (
+        "templates/layout.html",
+        include_str!("scaffold/layout.html"),
+    ),
+    (
+        "src/assets/css/global.css",
+        include_str!("scaffold/global.css"),
+    ),
+    ("src/index.djot", include_str!("scaffold/index.djot")),
+];
+
+pub fn init_command(path: &Path, name: Option<&str>) -> Result<()> {
+    if path.join("src").exists() || path.join("templates").exists() {
+        anyhow::bail!("{path:?} already contains a workspace");
+    }
+
+    std::fs::create_dir_all(path).with_context(|| format!("failed to create {path:?}"))?;
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {path:?}"))?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("can't name a workspace at {path:?}, use --name"))?,
+    };
+
+    let mut config = Config::load_or_default().context("failed to load configuration")?;
+    if config.workspaces.contains_key(&name) {
+        anyhow::bail!(
+            "workspace `{name}` already exists in {:?}",
+            config::get_config_path()
+        );
+    }
+
+    for (file, content) in SCAFFOLD {
+        let file_path = path.join(file);
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {parent:?}"))?;
+        }
+        std::fs::write(&file_path, content)
+            .with_context(|| format!("failed to write {file_path:?}"))?;
+    }
+
+    config
+        .workspaces
+        .insert(name.clone(), Workspace { path: path.clone() });
+    let config_path = config.save().context("failed to save configuration")?;
+
+    println!("created workspace `{name}` at {path:?}");
+    println!("registered in {config_path:?}");
+    println!("run `arrow serve -e {name}` to preview it.");
+    Ok(())
+}
+
+pub fn templates_check_command(entry: &str) -> Result<()> {
+    let workspace_path = resolve_entry_path(entry)?;
+    let site_config = SiteConfig::load(&workspace_path)?;
+    let theme_dir = site_config.theme_dir(&workspace_path)?;
+    let templates_dir = site_config.dirs(&workspace_path).templates;
+    let tera = templates::load(&template_dirs(&templates_dir, theme_dir.as_deref()))?;
+
+    println!(
+        "{} template(s) are valid.",
+        tera.get_template_names().count()
+    );
+    Ok(())
+}
+
+/// Lists the config files arrow reads, in the order they are merged.
+pub fn config_path_command() -> Result<()> {
+    for file in config::config_files() {
+        if file.path.exists() {
+            println!("{} ({})", file.path.display(), file.origin);
+        } else {
+            println!("{} ({}, not found)", file.path.display(), file.origin);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the global config, or with `resolved` every effective setting and
+/// where it came from, including the site settings of `entry` if given.
+pub fn config_show_command(resolved: bool, entry: Option<&str>, env: &str) -> Result<()> {
+    if !resolved {
+        let config = Config::load_config().context("failed to load configuration")?;
+        print!("{}", serde_yaml::to_string(&config)?);
+        return Ok(());
+    }
+
+    let config = Config::load_or_overrides().context("failed to load configuration")?;
+    println!("# global config");
+    for line in config::describe(&config, &Config::layers()?)? {
+        println!("{line}");
+    }
+
+    if let Some(entry) = entry {
+        let workspace_path = resolve_entry_path(entry)?;
+        let site_config = SiteConfig::load_env(&workspace_path, Some(env))?;
+        let layers = SiteConfig::layers(&workspace_path, Some(env))?;
+        println!();
+        println!("# site config ({env})");
+        for line in config::describe(&site_config, &layers)? {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+pub fn workspace_add_command(name: &str, path: &Path) -> Result<()> {
+    let mut config = Config::load_or_default().context("failed to load configuration")?;
+    if config.workspaces.contains_key(name) {
+        anyhow::bail!("workspace `{name}` already exists");
+    }
+    if !path.is_dir() {
+        anyhow::bail!("{path:?} is not a directory");
+    }
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {path:?}"))?;
+    let src_dir = SiteConfig::load(&path)?.dirs(&path).source;
+    if !src_dir.is_dir() {
+        eprintln!("warning: {path:?} has no source directory {src_dir:?} yet");
+    }
+
+    config
+        .workspaces
+        .insert(name.to_string(), Workspace { path: path.clone() });
+    let config_path = config.save().context("failed to save configuration")?;
+    println!("added workspace `{name}` at {path:?} to {config_path:?}");
+    Ok(())
+}
+
+pub fn workspace_remove_command(name: &str) -> Result<()> {
+    let mut config = Config::load_or_default().context("failed to load configuration")?;
+    if config.workspaces.shift_remove(name).is_none() {
+        return Err(workspace_not_editable(name));
+    }
+    let config_path = config.save().context("failed to save configuration")?;
+    println!("removed workspace `{name}` from {config_path:?}");
+    Ok(())
+}
+
+/// Moves the workspace to the top of the config, which makes it the default.
+pub fn workspace_set_default_command(name: &str) -> Result<()> {
+    let mut config = Config::load_or_default().context("failed to load configuration")?;
+    let index = config
+        .workspaces
+        .get_index_of(name)
+        .ok_or_else(|| workspace_not_editable(name))?;
+    config.workspaces.move_index(index, 0);
+    let config_path = config.save().context("failed to save configuration")?;
+    println!("`{name}` is now the default workspace in {config_path:?}");
+    Ok(())
+}
+
+/// Error for a workspace missing from the config file that commands change,
+/// pointing at the system config when it is defined there.
+fn workspace_not_editable(name: &str) -> anyhow::Error {
+    if let Ok(Some((path, system))) = config::system_config()
+        && system.workspaces.contains_key(name)
+    {
+        return anyhow::anyhow!(
+            "workspace `{name}` is defined in the system config {path:?}, which arrow doesn't change"
+        );
+    }
+    anyhow::anyhow!(
+        "workspace `{name}` not found in {:?}",
+        config::get_config_path()
+    )
+}
+
+pub fn lint_command(entry: &str) -> Result<()> {
+    let workspace_path = resolve_entry_path(entry)?;
+    let site_config = SiteConfig::load(&workspace_path)?;
+    let src_dir = site_config.dirs(&workspace_path).source;
+    let walker = fs::Walker::new(&workspace_path, &site_config)?;
+
+    let files = fs::collect_djot_files(&src_dir, &walker).context("error collecting Djot files")?;
+    let issues = lint_files(&src_dir, &files, &site_config)?;
+
+    for issue in &issues {
+        eprintln!("{issue}");
+    }
+
+    if !issues.is_empty() {
+        anyhow::bail!("found {} front matter problem(s)", issues.len());
+    }
+
+    println!("front matter of {} file(s) is valid.", files.len());
+    Ok(())
+}
+
+/// Lints the front matter of every file, as `path:line: message` lines.
+fn lint_files(
+    src_dir: &std::path::Path,
+    files: &[PathBuf],
+    site_config: &SiteConfig,
+) -> Result<Vec<String>> {
+    let mut issues = Vec::new();
+
+    for file in files {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read file {file:?}"))?;
+        let relative_path = file.strip_prefix(src_dir).unwrap_or(file).display();
+        for issue in lint::lint_front_matter(&content, &site_config.fields) {
+            issues.push(format!("{relative_path}:{}: {}", issue.line, issue.message));
+        }
+    }
+
+    issues.sort();
+    Ok(issues)
+}
+
+pub async fn check_command(entry: &str, external: bool) -> Result<()> {
+    let build_path = resolve_entry_path(entry)?;
+    let dist_dir = SiteConfig::load(&build_path)?.dirs(&build_path).output;
+
+    if !dist_dir.exists() {
+        anyhow::bail!(
+            "missing `{}` directory. run `arrow build` first.",
+            dist_dir
+                .strip_prefix(&build_path)
+                .unwrap_or(&dist_dir)
+                .display()
+        );
+    }
+
+    check_output(&dist_dir, external).await
+}
+
+async fn check_output(dist_dir: &std::path::Path, external: bool) -> Result<()> {
+    let problems = check::check_output(dist_dir, external)
+        .await
+        .context("error checking output")?;
+
+    for problem in &problems {
+        eprintln!(
+            "{}:{}: `{}`: {}",
+            problem.file.display(),
+            problem.line,
+            problem.target,
+            problem.reason
+        );
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!("found {} broken link(s)", problems.len());
+    }
+
+    println!("no broken links found.");
     Ok(())
 }
 
@@ -60,7 +470,9 @@ pub fn new_command(entry: &str) -> Result<()> {
     use std::io::Write;
 
     let workspace_path = resolve_entry_path(entry)?;
-    let src_path = workspace_path.join("src");
+    let src_path = SiteConfig::load(&workspace_path)?
+        .dirs(&workspace_path)
+        .source;
 
     let file_name = Text::new("enter file name (without extension):")
         .with_validator(|input: &str| {
@@ -127,9 +539,11 @@ pub fn status_command(entry: &str) -> Result<()> {
     use colored::Colorize;
 
     let workspace_path = resolve_entry_path(entry)?;
-    let src_dir = workspace_path.join("src");
+    let site_config = SiteConfig::load(&workspace_path)?;
+    let src_dir = site_config.dirs(&workspace_path).source;
+    let walker = fs::Walker::new(&workspace_path, &site_config)?;
 
-    let files = fs::collect_djot_files(&src_dir).context("error collecting Djot files")?;
+    let files = fs::collect_djot_files(&src_dir, &walker).context("error collecting Djot files")?;
 
     let mut entries: Vec<(PathBuf, bool)> = Vec::new();
 
@@ -140,7 +554,7 @@ pub fn status_command(entry: &str) -> Result<()> {
         entries.push((file, metadata.status));
     }
 
-    entries.sort_by(|a, b| b.1.cmp(&a.1));
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.1));
 
     println!("{:<60} {:<10}", "file Path", "status");
     println!("{}", "-".repeat(75));
@@ -162,7 +576,95 @@ pub fn status_command(entry: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn serve_command(port: u16, entry: &str) -> Result<()> {
+pub fn set_status_command(
+    entry: &str,
+    patterns: &[String],
+    status: bool,
+    set_date: bool,
+    dry_run: bool,
+) -> Result<()> {
+    use chrono::Local;
+    use colored::Colorize;
+
+    let workspace_path = resolve_entry_path(entry)?;
+    let src_dir = SiteConfig::load(&workspace_path)?
+        .dirs(&workspace_path)
+        .source;
+
+    let mut fields = vec![("status", status.to_string())];
+    if set_date {
+        fields.push(("date", Local::now().format("%Y-%m-%d").to_string()));
+    }
+
+    for file in resolve_source_patterns(&src_dir, patterns)? {
+        let relative_path = file.strip_prefix(&src_dir).unwrap_or(&file).display();
+        let content = std::fs::read_to_string(&file)
+            .with_context(|| format!("failed to read file {file:?}"))?;
+        let (updated, changes) = djot::set_front_matter_fields(&content, &fields)
+            .with_context(|| format!("failed to update front matter of {file:?}"))?;
+
+        if changes.is_empty() {
+            println!("unchanged: {relative_path}");
+            continue;
+        }
+
+        if dry_run {
+            println!("--- {relative_path}");
+            println!("+++ {relative_path}");
+            for change in &changes {
+                if let Some(old) = &change.old {
+                    println!("{}", format!("-{old}").red());
+                }
+                println!("{}", format!("+{}", change.new).green());
+            }
+            continue;
+        }
+
+        std::fs::write(&file, updated).with_context(|| format!("failed to write file {file:?}"))?;
+
+        let verb = if status { "published" } else { "unpublished" };
+        println!("{verb}: {relative_path}");
+    }
+
+    Ok(())
+}
+
+/// Expands source paths and glob patterns, trying them relative to the current
+/// directory first and to the workspace `src` directory second.
+fn resolve_source_patterns(src_dir: &std::path::Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        let mut matches = expand_glob(pattern)?;
+        if matches.is_empty() {
+            let in_src = src_dir.join(pattern);
+            matches = expand_glob(&in_src.to_string_lossy())?;
+        }
+
+        matches.retain(|path| path.extension().is_some_and(|ext| ext == "djot"));
+        if matches.is_empty() {
+            anyhow::bail!("no djot source files match `{pattern}`");
+        }
+
+        for path in matches {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    let paths = glob::glob(pattern).with_context(|| format!("invalid pattern `{pattern}`"))?;
+    Ok(paths
+        .filter_map(|path| path.ok())
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+pub async fn serve_command(port: u16, entry: &str, env: &str) -> Result<()> {
     use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
     use std::convert::Infallible;
     use std::sync::{
@@ -175,15 +677,20 @@ pub async fn serve_command(port: u16, entry: &str) -> Result<()> {
     use warp::Filter;
 
     println!("building initial site...");
-    build_command(entry).await.context("initial build failed")?;
+    build_command(entry, false, env, None, true)
+        .await
+        .context("initial build failed")?;
 
     let build_path = resolve_entry_path(entry)?;
+    let dirs = SiteConfig::load_env(&build_path, Some(env))?.dirs(&build_path);
 
-    let src_dir = build_path.join("src");
-    let dist_dir = build_path.join("dist");
+    let src_dir = dirs.source;
+    let dist_dir = dirs.output;
 
     let server_port = if port == 0 {
-        Config::load_config().map(|c| c.server.port).unwrap_or(8000)
+        Config::load_or_overrides()
+            .map(|c| c.server.port)
+            .unwrap_or(8000)
     } else {
         port
     };
@@ -199,6 +706,19 @@ pub async fn serve_command(port: u16, entry: &str) -> Result<()> {
         .watch(&src_dir, RecursiveMode::Recursive)
         .context("failed to start watching source directory")?;
 
+    // Any change to static files, data files, themes, templates or assets
+    // kept outside the source directory triggers a rebuild.
+    let mut watched_dirs = Vec::new();
+    let extra_dirs = ["static", "data", "themes"].map(|name| build_path.join(name));
+    for dir in extra_dirs.into_iter().chain([dirs.templates, dirs.assets]) {
+        if dir.exists() && !dir.starts_with(&src_dir) {
+            watcher
+                .watch(&dir, RecursiveMode::Recursive)
+                .with_context(|| format!("failed to start watching {dir:?}"))?;
+            watched_dirs.extend(dir.canonicalize().ok());
+        }
+    }
+
     let dist_dir_clone = dist_dir.clone();
     let dist_dir_canon = dist_dir.clone().canonicalize().unwrap();
 
@@ -271,6 +791,7 @@ pub async fn serve_command(port: u16, entry: &str) -> Result<()> {
     let server_handle = tokio::spawn(server);
 
     let entry_clone = entry.to_string();
+    let env = env.to_string();
     let mut last_rebuild = Instant::now();
     let debounce_duration = Duration::from_millis(200);
 
@@ -294,12 +815,17 @@ pub async fn serve_command(port: u16, entry: &str) -> Result<()> {
                                 if abs_path.starts_with(&dist_dir) {
                                     return false;
                                 }
+                                if watched_dirs.iter().any(|dir| abs_path.starts_with(dir)) {
+                                    return true;
+                                }
                             }
                             if let Some(file_name) = path.file_name() {
                                 let file_str = file_name.to_string_lossy();
                                 file_str.ends_with(".djot")
                                     || file_str.ends_with(".html")
                                     || file_str.ends_with(".css")
+                                    || file_str.ends_with(".scss")
+                                    || file_str.ends_with(".sass")
                                     || file_str.ends_with(".js")
                             } else {
                                 false
@@ -309,7 +835,9 @@ pub async fn serve_command(port: u16, entry: &str) -> Result<()> {
                         if has_relevant_files && last_rebuild.elapsed() > debounce_duration {
                             println!("change detected. Rebuilding...");
                             let rt = tokio::runtime::Runtime::new().unwrap();
-                            if let Err(e) = rt.block_on(build_command(&entry_clone)) {
+                            if let Err(e) =
+                                rt.block_on(build_command(&entry_clone, false, &env, None, true))
+                            {
                                 eprintln!("rebuild failed: {e}");
                             }
                             last_rebuild = Instant::now();
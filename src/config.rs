@@ -1,7 +1,46 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::lint::FieldSpec;
+use crate::menus::Menus;
+
+const SYSTEM_CONFIG: &str = "/etc/arrow/arrow.conf";
+
+/// Config file given with `--config`, which takes precedence over `ARROW_CONFIG`.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Values given with `--set`, applied over environment variable overrides.
+static SET_OVERRIDES: OnceLock<Vec<Override>> = OnceLock::new();
+
+/// Environment variables that override one setting each.
+const ENV_OVERRIDES: [(&str, &str); 2] = [
+    ("ARROW_SERVER_PORT", "server.port"),
+    ("ARROW_BASE_URL", "site.base_url"),
+];
+
+/// Top-level keys of the global config; every other key is a site setting.
+const GLOBAL_KEYS: [&str; 2] = ["workspaces", "server"];
+
+/// Settings from one place, such as a file or `--set`, merged over the
+/// layers before it.
+pub struct Layer {
+    /// Where the settings come from, e.g. a file path or `ARROW_BASE_URL`.
+    pub origin: String,
+    pub value: serde_yaml::Value,
+}
+
+/// One setting given on the command line or in the environment.
+struct Override {
+    /// Dotted path, e.g. `site.base_url`.
+    key: String,
+    value: serde_yaml::Value,
+    origin: String,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Workspace {
@@ -18,12 +57,255 @@ fn default_port() -> u16 {
     8000
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
+    /// In file order; the first one is the default.
     #[serde(default)]
-    pub workspaces: std::collections::HashMap<String, Workspace>,
+    pub workspaces: IndexMap<String, Workspace>,
     #[serde(default)]
     pub server: ServerConfig,
+    /// The file as loaded by `load_or_default`, so `save` keeps the keys
+    /// arrow doesn't know.
+    #[serde(skip)]
+    document: serde_yaml::Value,
+}
+
+/// Per-workspace settings, read from `site.conf` at the root of the workspace.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SiteConfig {
+    /// Site-wide values exposed to templates as `site`.
+    #[serde(default)]
+    pub site: SiteInfo,
+    /// Theme in `themes/<name>/`, providing `templates/` and `assets/`.
+    pub theme: Option<String>,
+    /// Menus exposed to templates as `menus`, merged with front matter `menu` entries.
+    #[serde(default)]
+    pub menus: Menus,
+    /// Inject self-link anchors next to headings.
+    #[serde(default)]
+    pub anchors: bool,
+    /// How Djot math is rendered.
+    #[serde(default)]
+    pub math: MathRenderer,
+    /// What to do with internal links to missing or unpublished pages.
+    #[serde(default)]
+    pub broken_links: BrokenLinks,
+    /// Write `graph.json` with the links between pages.
+    #[serde(default)]
+    pub graph: bool,
+    /// Custom front matter fields, checked by `arrow lint`.
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldSpec>,
+    /// Responsive image processing, off unless configured.
+    pub images: Option<ImageConfig>,
+    /// Minify CSS and JS assets and the generated HTML.
+    #[serde(default)]
+    pub minify: bool,
+    /// Add a content hash to CSS and JS file names and write `assets-manifest.json`.
+    #[serde(default)]
+    pub fingerprint: bool,
+    /// Follow symbolic links when walking `src/`.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// How assets are placed in `dist/`.
+    #[serde(default)]
+    pub copy: CopyMode,
+    /// Build pages whose front matter has `status: false`.
+    #[serde(default)]
+    pub drafts: bool,
+    /// Where the workspace keeps its sources, templates and output.
+    #[serde(default)]
+    pub dirs: DirsConfig,
+}
+
+/// Directories relative to the workspace root, or absolute.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct DirsConfig {
+    /// Djot pages, `src` by default.
+    pub source: Option<PathBuf>,
+    /// Built site, `dist` by default.
+    pub output: Option<PathBuf>,
+    /// Assets, `assets` inside the source directory by default.
+    pub assets: Option<PathBuf>,
+    /// Templates, `templates` by default.
+    pub templates: Option<PathBuf>,
+}
+
+/// The directories of a workspace, resolved against its root.
+#[derive(Debug, Clone)]
+pub struct WorkspaceDirs {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub assets: PathBuf,
+    pub templates: PathBuf,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SiteInfo {
+    #[serde(default)]
+    pub title: String,
+    /// Public URL of the site, e.g. `https://example.com`.
+    #[serde(default)]
+    pub base_url: String,
+    #[serde(default)]
+    pub description: String,
+    /// Any other values, such as `author`.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImageConfig {
+    /// Widths to generate for every image, in pixels. Widths at or above the
+    /// original width are skipped.
+    #[serde(default)]
+    pub widths: Vec<u32>,
+    /// Formats to generate in addition to the original one.
+    #[serde(default)]
+    pub formats: Vec<ImageOutput>,
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+    /// Drop EXIF and other metadata from full-size JPEG and PNG originals.
+    #[serde(default = "default_strip_exif")]
+    pub strip_exif: bool,
+    /// `sizes` attribute added to responsive Djot images.
+    #[serde(default)]
+    pub sizes: String,
+}
+
+fn default_quality() -> u8 {
+    80
+}
+
+fn default_strip_exif() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageOutput {
+    Webp,
+    Avif,
+}
+
+/// `hardlink` and `reflink` save space and time on large media, falling back to
+/// a copy where the file system does not support them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyMode {
+    #[default]
+    Copy,
+    Hardlink,
+    Reflink,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrokenLinks {
+    #[default]
+    Warn,
+    Error,
+}
+
+/// `mathml` renders math at build time; the others keep the `\(…\)` markup and
+/// load the named library on pages that contain math.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathRenderer {
+    #[default]
+    Mathml,
+    Katex,
+    Mathjax,
+}
+
+impl SiteConfig {
+    pub fn load(workspace_path: &Path) -> Result<Self> {
+        Self::load_env(workspace_path, None)
+    }
+
+    /// Loads `site.conf` with the settings under `environments.<env>` merged
+    /// over the others. `dev` and `prod` may be left out; any other
+    /// environment must be defined.
+    pub fn load_env(workspace_path: &Path, env: Option<&str>) -> Result<Self> {
+        let config_path = workspace_path.join("site.conf");
+        let value = merge_layers(Self::layers(workspace_path, env)?);
+        if value.is_null() {
+            return Ok(SiteConfig::default());
+        }
+        serde_yaml::from_value(value)
+            .map_err(|e| anyhow!("invalid site config {config_path:?}: {e}"))
+    }
+
+    /// The layers `load_env` merges: `site.conf`, its `environments.<env>`
+    /// section, then environment variable and `--set` overrides.
+    pub fn layers(workspace_path: &Path, env: Option<&str>) -> Result<Vec<Layer>> {
+        let config_path = workspace_path.join("site.conf");
+
+        let mut value = if config_path.exists() {
+            let data = fs::read_to_string(&config_path)?;
+            serde_yaml::from_str(&data)
+                .map_err(|e| anyhow!("invalid site config {:?}: {e}", config_path))?
+        } else {
+            serde_yaml::Value::Null
+        };
+
+        let mut environments = match &mut value {
+            serde_yaml::Value::Mapping(map) => map
+                .remove("environments")
+                .unwrap_or(serde_yaml::Value::Null),
+            _ => serde_yaml::Value::Null,
+        };
+        let mut layers = vec![Layer {
+            origin: config_path.display().to_string(),
+            value,
+        }];
+        if let Some(env) = env {
+            match environments.get_mut(env) {
+                Some(overrides) => layers.push(Layer {
+                    origin: format!("{} (environments.{env})", config_path.display()),
+                    value: std::mem::take(overrides),
+                }),
+                None if matches!(env, "dev" | "prod") => {}
+                None => {
+                    return Err(anyhow!(
+                        "environment `{env}` is not defined in {config_path:?}"
+                    ));
+                }
+            }
+        }
+        layers.extend(override_layers(false)?);
+
+        Ok(layers)
+    }
+
+    pub fn dirs(&self, workspace_path: &Path) -> WorkspaceDirs {
+        let dir = |configured: &Option<PathBuf>, default: &str| {
+            workspace_path.join(configured.as_deref().unwrap_or(Path::new(default)))
+        };
+        let source = dir(&self.dirs.source, "src");
+        let assets = match &self.dirs.assets {
+            Some(assets) => workspace_path.join(assets),
+            None => source.join("assets"),
+        };
+        WorkspaceDirs {
+            output: dir(&self.dirs.output, "dist"),
+            templates: dir(&self.dirs.templates, "templates"),
+            source,
+            assets,
+        }
+    }
+
+    /// Directory of the configured theme, if any.
+    pub fn theme_dir(&self, workspace_path: &Path) -> Result<Option<PathBuf>> {
+        let Some(theme) = &self.theme else {
+            return Ok(None);
+        };
+        let dir = workspace_path.join("themes").join(theme);
+        if !dir.is_dir() {
+            return Err(anyhow!("theme `{theme}` not found at {dir:?}"));
+        }
+        Ok(Some(dir))
+    }
 }
 
 impl Default for ServerConfig {
@@ -35,17 +317,105 @@ impl Default for ServerConfig {
 }
 
 impl Config {
+    /// Loads the system config and then the user config over it, or only the
+    /// file given with `--config` or `ARROW_CONFIG`.
     pub fn load_config() -> Result<Self> {
-        let config_path = get_config_path();
+        if !config_files().iter().any(|file| file.path.exists()) {
+            return Err(anyhow!("config file not found at {:?}", get_config_path()));
+        }
+        Self::from_layers(Self::layers()?)
+    }
+
+    /// Like `load_config`, but without a config file only the overrides apply.
+    pub fn load_or_overrides() -> Result<Self> {
+        Self::from_layers(Self::layers()?)
+    }
 
+    /// The config files that exist, then environment variable and `--set`
+    /// overrides.
+    pub fn layers() -> Result<Vec<Layer>> {
+        let mut layers = Vec::new();
+        for file in config_files() {
+            if !file.path.exists() {
+                continue;
+            }
+            let data = fs::read_to_string(&file.path)
+                .with_context(|| format!("failed to read {:?}", file.path))?;
+            let value = serde_yaml::from_str(&data)
+                .map_err(|e| anyhow!("invalid config {:?}: {e}", file.path))?;
+            layers.push(Layer {
+                origin: file.path.display().to_string(),
+                value,
+            });
+        }
+        layers.extend(override_layers(true)?);
+        Ok(layers)
+    }
+
+    fn from_layers(layers: Vec<Layer>) -> Result<Self> {
+        let value = merge_layers(layers);
+        if value.is_null() {
+            return Ok(Config::default());
+        }
+        Ok(serde_yaml::from_value(value)?)
+    }
+
+    /// Loads only the file that `save` writes to, or an empty config if it
+    /// doesn't exist yet, so system settings aren't copied into it.
+    pub fn load_or_default() -> Result<Self> {
+        let config_path = get_config_path();
         if !config_path.exists() {
-            return Err(anyhow!("config file not found at {:?}", config_path));
+            return Ok(Config::default());
+        }
+        let data = fs::read_to_string(&config_path)
+            .with_context(|| format!("failed to read {config_path:?}"))?;
+        let document: serde_yaml::Value = serde_yaml::from_str(&data)
+            .map_err(|e| anyhow!("invalid config {config_path:?}: {e}"))?;
+        if document.is_null() {
+            return Ok(Config::default());
+        }
+        let mut config: Config = serde_yaml::from_value(document.clone())
+            .map_err(|e| anyhow!("invalid config {config_path:?}: {e}"))?;
+        config.document = document;
+        Ok(config)
+    }
+
+    /// Writes the config to its file, creating the file and its directory if
+    /// needed. The new content is written next to the file and moved over it,
+    /// so a failed write leaves the old config intact. Only `workspaces` is
+    /// written; every other key of the file is kept as it was, but comments
+    /// are not.
+    pub fn save(&self) -> Result<PathBuf> {
+        let config_path = get_config_path();
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {parent:?}"))?;
         }
 
-        let data = fs::read_to_string(&config_path)?;
-        let cfg: Config = serde_yaml::from_str(&data)?;
+        let mut document = match &self.document {
+            serde_yaml::Value::Mapping(document) => document.clone(),
+            _ => serde_yaml::Mapping::new(),
+        };
+        let mut workspaces = document
+            .remove("workspaces")
+            .unwrap_or(serde_yaml::Value::Null);
+        if let serde_yaml::Value::Mapping(workspaces) = &mut workspaces {
+            workspaces.retain(|key, _| {
+                key.as_str()
+                    .is_some_and(|key| self.workspaces.contains_key(key))
+            });
+        }
+        let workspaces = merge(workspaces, serde_yaml::to_value(&self.workspaces)?);
+        let mut saved = serde_yaml::Mapping::new();
+        saved.insert("workspaces".into(), workspaces);
+        saved.extend(document);
+        let data = serde_yaml::to_string(&saved)?;
+        let tmp_path = config_path.with_extension("conf.tmp");
+        fs::write(&tmp_path, data).with_context(|| format!("failed to write {tmp_path:?}"))?;
+        fs::rename(&tmp_path, &config_path)
+            .with_context(|| format!("failed to replace {config_path:?}"))?;
 
-        Ok(cfg)
+        Ok(config_path)
     }
 
     pub fn get_path(&self, workspace_key: &str) -> Result<&PathBuf> {
@@ -75,18 +445,263 @@ impl Config {
     }
 }
 
-fn get_config_path() -> PathBuf {
-    if cfg!(windows) {
-        dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("C:\\"))
-            .join("AppData")
-            .join("Roaming")
-            .join("arrow")
-            .join("arrow.conf")
-    } else {
-        dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("/etc"))
-            .join("arrow")
-            .join("arrow.conf")
+/// Makes every later config lookup use `path`, for the `--config` flag.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+/// A config file arrow looks at, and why.
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// `--config`, `ARROW_CONFIG`, `system` or `user`.
+    pub origin: &'static str,
+}
+
+/// The config files to read, lowest precedence first: the file given with
+/// `--config` or `ARROW_CONFIG` alone, otherwise `/etc/arrow/arrow.conf`
+/// followed by the user config.
+pub fn config_files() -> Vec<ConfigFile> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return vec![ConfigFile {
+            path: path.clone(),
+            origin: "--config",
+        }];
+    }
+    if let Some(path) = env_config_path() {
+        return vec![ConfigFile {
+            path,
+            origin: "ARROW_CONFIG",
+        }];
+    }
+
+    let mut files = Vec::new();
+    if let Some(path) = system_config_path() {
+        files.push(ConfigFile {
+            path,
+            origin: "system",
+        });
+    }
+    if let Some(path) = user_config_path() {
+        files.push(ConfigFile {
+            path,
+            origin: "user",
+        });
+    }
+    files
+}
+
+/// The system config and its path, if it is read and exists.
+pub fn system_config() -> Result<Option<(PathBuf, Config)>> {
+    let Some(file) = config_files()
+        .into_iter()
+        .find(|file| file.origin == "system" && file.path.exists())
+    else {
+        return Ok(None);
+    };
+    let data = fs::read_to_string(&file.path)
+        .with_context(|| format!("failed to read {:?}", file.path))?;
+    let config =
+        serde_yaml::from_str(&data).map_err(|e| anyhow!("invalid config {:?}: {e}", file.path))?;
+    Ok(Some((file.path, config)))
+}
+
+/// The config file that commands such as `arrow workspace add` write to.
+pub fn get_config_path() -> PathBuf {
+    if let Some(path) = CONFIG_PATH.get() {
+        return path.clone();
+    }
+    env_config_path()
+        .or_else(user_config_path)
+        .unwrap_or_else(|| PathBuf::from(SYSTEM_CONFIG))
+}
+
+fn env_config_path() -> Option<PathBuf> {
+    std::env::var_os("ARROW_CONFIG")
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The file in `ARROW_SYSTEM_CONFIG` when set, otherwise `/etc/arrow/arrow.conf`
+/// on unix.
+fn system_config_path() -> Option<PathBuf> {
+    std::env::var_os("ARROW_SYSTEM_CONFIG")
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| cfg!(unix).then(|| PathBuf::from(SYSTEM_CONFIG)))
+}
+
+/// `$XDG_CONFIG_HOME/arrow/arrow.conf` when set, otherwise the platform's
+/// config directory: `~/.config` on Linux, `%APPDATA%` on Windows and
+/// `~/Library/Application Support` on macOS.
+fn user_config_path() -> Option<PathBuf> {
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute());
+    xdg.or_else(dirs::config_dir)
+        .map(|dir| dir.join("arrow").join("arrow.conf"))
+}
+
+/// Records the `KEY=VALUE` pairs given with `--set`.
+pub fn set_overrides(pairs: &[String]) -> Result<()> {
+    let overrides = pairs
+        .iter()
+        .map(|pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| anyhow!("invalid --set `{pair}`, expected KEY=VALUE"))?;
+            if !is_setting(key) {
+                return Err(anyhow!("invalid --set `{pair}`, unknown setting `{key}`"));
+            }
+            Ok(Override {
+                key: key.to_string(),
+                value: parse_value(value),
+                origin: format!("--set {key}"),
+            })
+        })
+        .collect::<Result<_>>()?;
+    let _ = SET_OVERRIDES.set(overrides);
+    Ok(())
+}
+
+/// Whether the first part of a dotted key is a global setting or a field of
+/// `site.conf`, so a typo doesn't pass as a setting nothing reads.
+fn is_setting(key: &str) -> bool {
+    let top = key.split('.').next().unwrap_or(key);
+    GLOBAL_KEYS.contains(&top)
+        || serde_yaml::to_value(SiteConfig::default()).is_ok_and(|fields| fields.get(top).is_some())
+}
+
+/// Reads a value as YAML, so `8080` and `true` keep their type, falling back
+/// to a plain string.
+fn parse_value(value: &str) -> serde_yaml::Value {
+    match serde_yaml::from_str(value) {
+        Ok(serde_yaml::Value::Null) | Err(_) => serde_yaml::Value::String(value.to_string()),
+        Ok(value) => value,
+    }
+}
+
+/// Overrides of either the global config or the site config, in order of
+/// precedence: environment variables, then `--set`.
+fn override_layers(global: bool) -> Result<Vec<Layer>> {
+    let mut overrides = Vec::new();
+    for (var, key) in ENV_OVERRIDES {
+        if let Some(value) = std::env::var_os(var).filter(|v| !v.is_empty()) {
+            let value = value
+                .into_string()
+                .map_err(|_| anyhow!("{var} is not valid UTF-8"))?;
+            overrides.push(Override {
+                key: key.to_string(),
+                value: parse_value(&value),
+                origin: var.to_string(),
+            });
+        }
+    }
+
+    let sets = SET_OVERRIDES.get().into_iter().flatten();
+    Ok(overrides
+        .iter()
+        .chain(sets)
+        .filter(|o| {
+            let top = o.key.split('.').next().unwrap_or_default();
+            GLOBAL_KEYS.contains(&top) == global
+        })
+        .map(|o| {
+            let value = o.key.rsplit('.').fold(o.value.clone(), |value, part| {
+                let mut map = serde_yaml::Mapping::new();
+                map.insert(serde_yaml::Value::String(part.to_string()), value);
+                serde_yaml::Value::Mapping(map)
+            });
+            Layer {
+                origin: o.origin.clone(),
+                value,
+            }
+        })
+        .collect())
+}
+
+fn merge_layers(layers: Vec<Layer>) -> serde_yaml::Value {
+    layers
+        .into_iter()
+        .fold(serde_yaml::Value::Null, |merged, layer| {
+            merge(merged, layer.value)
+        })
+}
+
+/// The effective settings as `key: value` lines, each followed by the layer
+/// that last set it, or `default`.
+pub fn describe<T: Serialize>(resolved: &T, layers: &[Layer]) -> Result<Vec<String>> {
+    let mut origins = BTreeMap::new();
+    for layer in layers {
+        let mut leaves = Vec::new();
+        flatten("", &layer.value, &mut leaves);
+        for (key, _) in leaves {
+            origins.insert(key, layer.origin.as_str());
+        }
+    }
+
+    let resolved = serde_yaml::to_value(resolved)?;
+    let mut leaves = Vec::new();
+    flatten("", &resolved, &mut leaves);
+    Ok(leaves
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_yaml::Value::String(s) if !s.is_empty() => s.clone(),
+                other => serde_json::to_string(other).unwrap_or_default(),
+            };
+            let origin = origins.get(&key).copied().unwrap_or("default");
+            format!("{key}: {value}  # {origin}")
+        })
+        .collect())
+}
+
+/// Collects the non-map values under `value` with their dotted keys.
+fn flatten<'a>(
+    prefix: &str,
+    value: &'a serde_yaml::Value,
+    leaves: &mut Vec<(String, &'a serde_yaml::Value)>,
+) {
+    match value {
+        serde_yaml::Value::Mapping(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = match key {
+                    serde_yaml::Value::String(s) => s.clone(),
+                    other => serde_json::to_string(other).unwrap_or_default(),
+                };
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&key, value, leaves);
+            }
+        }
+        serde_yaml::Value::Null => {}
+        _ => leaves.push((prefix.to_string(), value)),
+    }
+}
+
+/// Merges `overlay` over `base`. Maps are merged key by key, with the keys of
+/// `overlay` first so that its first workspace stays the default; any other
+/// value in `overlay` replaces the one in `base`.
+fn merge(base: serde_yaml::Value, overlay: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
+
+    match (base, overlay) {
+        (Value::Mapping(mut base), Value::Mapping(overlay)) => {
+            let mut merged = serde_yaml::Mapping::new();
+            for (key, value) in overlay {
+                let value = match base.remove(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => value,
+                };
+                merged.insert(key, value);
+            }
+            merged.extend(base);
+            Value::Mapping(merged)
+        }
+        (base, Value::Null) => base,
+        (_, overlay) => overlay,
     }
 }
@@ -0,0 +1,82 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::fs::Walker;
+
+/// Loads every YAML, JSON, TOML and CSV file under `data_dir` into one map
+/// keyed by file stem, with subdirectories as nested maps, so
+/// `data/social/links.yaml` becomes `data.social.links`.
+pub fn load(data_dir: &Path, walker: &Walker) -> Result<Value> {
+    let mut data = Map::new();
+    if !data_dir.exists() {
+        return Ok(Value::Object(data));
+    }
+
+    for file in walker.files(data_dir)? {
+        let Some(ext) = file.extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+        if !matches!(ext, "yaml" | "yml" | "json" | "toml" | "csv") {
+            continue;
+        }
+
+        let content =
+            fs::read_to_string(&file).with_context(|| format!("failed to read {file:?}"))?;
+        let value = parse(&content, ext).with_context(|| format!("invalid data file {file:?}"))?;
+
+        let relative = file
+            .strip_prefix(data_dir)
+            .context("failed to strip prefix")?;
+        let mut table = &mut data;
+        if let Some(parent) = relative.parent() {
+            for dir in parent.iter() {
+                let dir = dir.to_string_lossy().into_owned();
+                let entry = table
+                    .entry(dir)
+                    .or_insert_with(|| Value::Object(Map::new()));
+                table = entry
+                    .as_object_mut()
+                    .ok_or_else(|| anyhow!("data file and directory share a name: {file:?}"))?;
+            }
+        }
+
+        let stem = relative
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        if table.insert(stem, value).is_some() {
+            return Err(anyhow!(
+                "more than one data file named {:?}",
+                relative.with_extension("")
+            ));
+        }
+    }
+
+    Ok(Value::Object(data))
+}
+
+fn parse(content: &str, ext: &str) -> Result<Value> {
+    Ok(match ext {
+        "json" => serde_json::from_str(content)?,
+        "toml" => toml::from_str(content)?,
+        "csv" => {
+            let mut reader = csv::Reader::from_reader(content.as_bytes());
+            let headers = reader.headers()?.clone();
+            let mut rows = Vec::new();
+            for record in reader.records() {
+                let record = record?;
+                let row: Map<String, Value> = headers
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+                    .collect();
+                rows.push(Value::Object(row));
+            }
+            Value::Array(rows)
+        }
+        _ => serde_yaml::from_str(content)?,
+    })
+}
@@ -1,16 +1,25 @@
 use anyhow::{Context, Ok, Result, anyhow};
 use chrono::NaiveDate;
-use jotdown::Parser;
 use jotdown::html::render_to_string;
+use jotdown::{AttributeKind, Attributes, Container, Event, LinkType, Parser, SpanLinkType};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 
 use std::path::{Path, PathBuf};
 use syntect::highlighting::ThemeSet;
 use syntect::html::{ClassStyle, css_for_theme_with_class_style};
-use tera::{Context as TeraContext, Tera};
+use tera::Tera;
 
-#[derive(Debug, Deserialize, Serialize)]
+use crate::config::{BrokenLinks, MathRenderer, SiteConfig};
+use crate::images::{self, ImageSet, ProcessedImage};
+use crate::links::{self, LinkIndex, PageLink, TextPiece};
+use crate::menus::MenuRef;
+use crate::shortcodes::{self, OpenShortcode};
+use crate::site::{Site, SourceFile};
+use crate::{assets, functions, math, templates};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Metadata {
     pub title: Option<String>,
     pub desc: Option<String>,
@@ -18,6 +27,11 @@ pub struct Metadata {
     #[serde(default = "default_status")]
     pub status: bool,
     pub js: Option<String>,
+    pub toc: Option<bool>,
+    pub menu: Option<MenuRef>,
+    /// Custom fields declared in the site config.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 fn default_status() -> bool {
@@ -27,55 +41,172 @@ fn default_status() -> bool {
 #[derive(Debug, Serialize)]
 pub struct Page {
     pub title: String,
+    /// Absolute URL of the page, e.g. `/posts/trip.html`.
+    pub url: String,
     pub desc: String,
     pub date: String,
     pub content: String,
     pub inline_css: String,
     pub inline_js: String,
     pub assets_path: String,
+    pub toc: Vec<TocEntry>,
+    pub has_math: bool,
+    pub word_count: usize,
+    /// Minutes, see `functions::reading_time`.
+    pub reading_time: usize,
+    /// Excerpt for listing pages, as HTML.
+    pub summary: String,
+    pub backlinks: Vec<PageLink>,
+    /// `status: false`, for pages built because `drafts` is on.
+    pub draft: bool,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A heading collected from the document, with the headings nested below it.
+#[derive(Debug, Serialize)]
+pub struct TocEntry {
+    pub level: u16,
+    pub text: String,
+    pub id: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// A link to a missing or unpublished page with `broken_links: error`, which
+/// fails the build rather than only the page.
+#[derive(Debug)]
+pub struct BrokenLinkError(String);
+
+impl std::fmt::Display for BrokenLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BrokenLinkError {}
+
+struct RenderOptions<'a> {
+    anchors: bool,
+    math: MathRenderer,
+    broken_links: BrokenLinks,
+    tera: &'a Tera,
+    links: &'a LinkIndex,
+    images: &'a ImageSet,
+    image_sizes: &'a str,
+    /// Source file relative to `src` and the line its Djot body starts on.
+    source: &'a Path,
+    first_line: usize,
+    /// Skip broken link reports, for renders whose HTML isn't written.
+    quiet: bool,
+}
+
+impl<'a> RenderOptions<'a> {
+    fn new(
+        source: &'a SourceFile,
+        config: &'a SiteConfig,
+        tera: &'a Tera,
+        links: &'a LinkIndex,
+        images: &'a ImageSet,
+    ) -> Self {
+        RenderOptions {
+            anchors: config.anchors,
+            math: config.math,
+            broken_links: config.broken_links,
+            tera,
+            links,
+            images,
+            image_sizes: config
+                .images
+                .as_ref()
+                .map_or("", |images| images.sizes.as_str()),
+            source: &source.relative,
+            first_line: source.body_line(),
+            quiet: false,
+        }
+    }
+
+    /// Reports a link that does not lead to a published page, failing the page if
+    /// the site is configured to.
+    fn broken_link(&self, line: usize, message: String) -> Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        let location = format!("{}:{line}", self.source.display());
+        match self.broken_links {
+            BrokenLinks::Warn => {
+                eprintln!("warning: {location}: {message}");
+                Ok(())
+            }
+            BrokenLinks::Error => Err(BrokenLinkError(format!("{location}: {message}")).into()),
+        }
+    }
+
+    fn line_at(&self, content: &str, offset: usize) -> usize {
+        self.first_line + content[..offset].matches('\n').count()
+    }
+}
+
+struct RenderedDjot {
+    html: String,
+    toc: Vec<TocEntry>,
+    has_math: bool,
+    summary: Summary,
+}
+
+/// Length and excerpt of a document, taken from its events.
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    /// Words of prose, leaving out code and raw HTML.
+    pub word_count: usize,
+    /// HTML before the `{% more %}` marker, or else of the first paragraph.
+    pub html: Option<String>,
 }
 
-pub fn process_djot_file(djot_path: &Path, src_dir: &Path, dist_dir: &Path) -> Result<()> {
-    let content = fs::read_to_string(djot_path)
-        .with_context(|| format!("failed to read file {djot_path:?}"))?;
+impl Summary {
+    /// The excerpt, falling back to the page description.
+    pub fn html_or(&self, desc: Option<&str>) -> String {
+        match (&self.html, desc) {
+            (Some(html), _) => html.clone(),
+            (None, Some(desc)) => format!("<p>{}</p>", tera::escape_html(desc)),
+            (None, None) => String::new(),
+        }
+    }
+}
 
-    let (metadata, djot_content) = parse_front_matter(&content)?;
+pub fn process_djot_file(source: &SourceFile, site: &Site) -> Result<()> {
+    let metadata = &source.metadata;
 
-    if !metadata.status {
+    if !source.published {
         return Ok(());
     }
 
-    let title = get_default_title(djot_path, metadata.title);
-    let html_content = djot_to_html(&djot_content)?;
+    let options = RenderOptions::new(source, &site.config, &site.tera, &site.links, &site.images);
+    let mut rendered = djot_to_html(source.body(), &options)?;
+    if !metadata.toc.unwrap_or(true) {
+        rendered.toc.clear();
+    }
 
     let theme_set = ThemeSet::load_defaults();
     let theme = &theme_set.themes["base16-ocean.dark"];
 
     let inline_css = css_for_theme_with_class_style(theme, ClassStyle::Spaced)?;
-    let inline_js = metadata.js.unwrap_or_default();
+    let inline_js = metadata.js.clone().unwrap_or_default();
 
-    let page = create_page(
-        title,
-        metadata.desc,
-        metadata.date,
-        html_content,
-        inline_css,
-        inline_js,
-    );
+    let page = create_page(source, rendered, inline_css, inline_js);
 
-    let dest_path = get_dest_path(djot_path, src_dir, dist_dir)?;
+    let dest_path = get_dest_path(&source.path, &site.src_dir, &site.dist_dir)?;
 
     if let Some(parent) = dest_path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("failed to create directory {parent:?}"))?;
     }
 
-    let site_root = src_dir
-        .parent()
-        .ok_or_else(|| anyhow!("could not determine site root"))?;
-    let template_dir = site_root.join("templates");
+    let head_assets = if page.has_math {
+        math::client_assets(site.config.math)
+    } else {
+        ""
+    };
 
-    render_html_page(&page, &dest_path, &template_dir)?;
+    render_html_page(&page, &dest_path, site, head_assets)?;
 
     Ok(())
 }
@@ -96,6 +227,103 @@ pub fn parse_front_matter(content: &str) -> Result<(Metadata, String)> {
     Ok((metadata, djot_content))
 }
 
+/// A single line rewritten by [`set_front_matter_fields`]. `old` is `None` when
+/// the field was not present and had to be inserted.
+#[derive(Debug)]
+pub struct FieldChange {
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// Sets top-level front matter fields to the given raw YAML values, leaving every
+/// other byte of the file untouched. Fields that are missing are appended to the
+/// end of the front matter block, which is created if the file has none.
+pub fn set_front_matter_fields(
+    content: &str,
+    fields: &[(&str, String)],
+) -> Result<(String, Vec<FieldChange>)> {
+    parse_front_matter(content)?;
+
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let Some((start, end)) = front_matter_range(content) else {
+        let mut block = format!("---{newline}");
+        let mut changes = Vec::new();
+        for (key, value) in fields {
+            let line = format!("{key}: {value}");
+            block.push_str(&line);
+            block.push_str(newline);
+            changes.push(FieldChange {
+                old: None,
+                new: line,
+            });
+        }
+        block.push_str(&format!("---{newline}"));
+        return Ok((block + content, changes));
+    };
+
+    let mut lines: Vec<String> = content[start..end]
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect();
+    let mut changes = Vec::new();
+
+    for (key, value) in fields {
+        let existing = lines.iter().position(|line| {
+            line.strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start_matches([' ', '\t']).starts_with(':'))
+        });
+
+        match existing {
+            Some(index) => {
+                let line = &lines[index];
+                let body = line.trim_end_matches(['\r', '\n']);
+                let ending = &line[body.len()..];
+                let new = format!("{key}: {value}");
+                if body != new {
+                    changes.push(FieldChange {
+                        old: Some(body.to_string()),
+                        new: new.clone(),
+                    });
+                    lines[index] = format!("{new}{ending}");
+                }
+            }
+            None => {
+                if let Some(last) = lines.last_mut()
+                    && !last.ends_with('\n')
+                {
+                    last.push_str(newline);
+                }
+                let new = format!("{key}: {value}");
+                lines.push(format!("{new}{newline}"));
+                changes.push(FieldChange { old: None, new });
+            }
+        }
+    }
+
+    let updated = format!("{}{}{}", &content[..start], lines.concat(), &content[end..]);
+    Ok((updated, changes))
+}
+
+/// Byte range of the YAML between the opening and closing `---` markers, split the
+/// same way as [`parse_front_matter`].
+pub fn front_matter_range(content: &str) -> Option<(usize, usize)> {
+    let open = content.find("---")?;
+    let start = open + 3;
+    let close = content[start..].find("---")?;
+    let mut start = start;
+    if content[start..].starts_with("\r\n") {
+        start += 2;
+    } else if content[start..].starts_with('\n') {
+        start += 1;
+    }
+    Some((start, (open + 3 + close).max(start)))
+}
+
 impl Default for Metadata {
     fn default() -> Self {
         Metadata {
@@ -104,11 +332,14 @@ impl Default for Metadata {
             date: None,
             status: default_status(),
             js: None,
+            toc: None,
+            menu: None,
+            extra: BTreeMap::new(),
         }
     }
 }
 
-fn get_default_title(path: &Path, title: Option<String>) -> String {
+pub fn get_default_title(path: &Path, title: Option<String>) -> String {
     title.unwrap_or_else(|| {
         path.file_stem()
             .and_then(|s| s.to_str())
@@ -117,31 +348,385 @@ fn get_default_title(path: &Path, title: Option<String>) -> String {
     })
 }
 
-fn djot_to_html(content: &str) -> Result<String> {
-    let parser = Parser::new(content);
-    Ok(render_to_string(parser))
+fn djot_to_html(content: &str, options: &RenderOptions) -> Result<RenderedDjot> {
+    let mut events = Vec::new();
+    let mut toc = Vec::new();
+    let mut heading: Option<(u16, String, String)> = None;
+    let mut math: Option<(bool, String)> = None;
+    let mut has_math = false;
+    let mut open_spans: Vec<Option<OpenShortcode>> = Vec::new();
+    let mut link_depth = 0;
+    let mut literal_depth = 0;
+    let mut image: Option<(&ProcessedImage, String, String)> = None;
+
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        if let Some((processed, attributes, alt)) = image.as_mut() {
+            if let Event::End(Container::Image(..)) = event {
+                let html = images::image_html(processed, alt, attributes, options.image_sizes);
+                push_raw_html(&mut events, html);
+                image = None;
+            } else if let Some(text) = plain_text(&event) {
+                alt.push_str(text);
+            }
+            continue;
+        }
+
+        if let Some((display, latex)) = math.as_mut() {
+            match &event {
+                Event::Str(s) => latex.push_str(s),
+                Event::End(Container::Math { .. }) => {
+                    let mathml = math::to_mathml(latex, *display)?;
+                    push_raw_html(&mut events, mathml);
+                    math = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match &event {
+            Event::Start(container @ (Container::Div { .. } | Container::Span), attributes) => {
+                let line = options.line_at(content, range.start);
+                let shortcode =
+                    shortcodes::lookup(options.tera, container, attributes, line, events.len());
+                if shortcode.is_some() {
+                    open_spans.push(shortcode);
+                    continue;
+                }
+                open_spans.push(None);
+            }
+            Event::End(Container::Div { .. } | Container::Span) => {
+                if let Some(shortcode) = open_spans.pop().flatten() {
+                    let block = shortcode.block;
+                    let mut body = render_to_string(events.drain(shortcode.body_start..));
+                    if !block {
+                        body.truncate(body.trim_end().len());
+                    }
+                    let source = options.source.display().to_string();
+                    let html = shortcodes::render(options.tera, shortcode, &body, &source)?;
+                    if block {
+                        push_raw_block(&mut events, html);
+                    } else {
+                        push_raw_html(&mut events, html);
+                    }
+                    continue;
+                }
+            }
+            Event::Start(Container::Link(destination, LinkType::Span(kind)), attributes)
+                if links::is_source_link(destination) =>
+            {
+                link_depth += 1;
+                let line = options.line_at(content, range.start);
+                let destination = resolve_source_link(options, destination, line)?;
+                let link = Container::Link(destination.into(), LinkType::Span(*kind));
+                events.push(Event::Start(link, attributes.clone()));
+                continue;
+            }
+            Event::Start(Container::Image(src, _), attributes)
+                if options.images.contains_key(src.as_ref()) =>
+            {
+                let rendered_attributes = attributes
+                    .unique_pairs()
+                    .map(|(key, value)| {
+                        format!(" {key}=\"{}\"", tera::escape_html(&value.to_string()))
+                    })
+                    .collect();
+                image = Some((
+                    &options.images[src.as_ref()],
+                    rendered_attributes,
+                    String::new(),
+                ));
+                continue;
+            }
+            Event::Start(Container::Link(..), _) => link_depth += 1,
+            Event::End(Container::Link(..)) => link_depth -= 1,
+            Event::Start(container, _) if links::is_literal(container) => literal_depth += 1,
+            Event::End(container) if links::is_literal(container) => literal_depth -= 1,
+            Event::Str(text) if link_depth == 0 && literal_depth == 0 && text.contains("[[") => {
+                for piece in links::split_wiki_links(text) {
+                    let (target, label, offset) = match piece {
+                        TextPiece::Text(text) => {
+                            if let Some((_, _, heading_text)) = heading.as_mut() {
+                                heading_text.push_str(text);
+                            }
+                            events.push(Event::Str(text.to_string().into()));
+                            continue;
+                        }
+                        TextPiece::Wiki {
+                            target,
+                            label,
+                            offset,
+                        } => (target, label, offset),
+                    };
+
+                    let line = options.line_at(content, range.start + offset);
+                    let resolved = options.links.resolve_wiki(options.source, target);
+                    let Some((found, by_path)) = resolved else {
+                        options.broken_link(line, format!("no page matches `[[{target}]]`"))?;
+                        let literal = &text[offset..];
+                        let literal = &literal[..literal.find("]]").unwrap_or(0) + 2];
+                        events.push(Event::Str(literal.to_string().into()));
+                        continue;
+                    };
+                    if !found.published {
+                        let message = format!("`[[{target}]]` links to an unpublished page");
+                        options.broken_link(line, message)?;
+                    }
+
+                    let label = match label {
+                        Some(label) => label.to_string(),
+                        None if by_path => found.title.clone(),
+                        None => target.trim().to_string(),
+                    };
+                    if let Some((_, _, heading_text)) = heading.as_mut() {
+                        heading_text.push_str(&label);
+                    }
+
+                    let link = Container::Link(
+                        found.url.clone().into(),
+                        LinkType::Span(SpanLinkType::Inline),
+                    );
+                    let mut attributes = Attributes::new();
+                    attributes.push((jotdown::AttributeKind::Class, "wikilink".into()));
+                    events.push(Event::Start(link.clone(), attributes));
+                    events.push(Event::Str(label.into()));
+                    events.push(Event::End(link));
+                }
+                continue;
+            }
+            Event::Start(Container::Math { display }, _) => {
+                has_math = true;
+                if options.math == MathRenderer::Mathml {
+                    math = Some((*display, String::new()));
+                    continue;
+                }
+            }
+            Event::Start(Container::Heading { level, id, .. }, _) => {
+                heading = Some((*level, id.to_string(), String::new()));
+            }
+            Event::End(Container::Heading { .. }) => {
+                if let Some((level, id, text)) = heading.take() {
+                    if options.anchors {
+                        let anchor = format!(
+                            " <a class=\"anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+                            tera::escape_html(&id)
+                        );
+                        push_raw_html(&mut events, anchor);
+                    }
+                    insert_toc_entry(
+                        &mut toc,
+                        TocEntry {
+                            level,
+                            text: text.trim().to_string(),
+                            id,
+                            children: Vec::new(),
+                        },
+                    );
+                }
+            }
+            _ => {
+                if let Some((_, _, text)) = heading.as_mut()
+                    && let Some(plain) = plain_text(&event)
+                {
+                    text.push_str(plain);
+                }
+            }
+        }
+        events.push(event);
+    }
+
+    Ok(RenderedDjot {
+        summary: summarize(&events),
+        html: render_to_string(events.into_iter()),
+        toc,
+        has_math,
+    })
+}
+
+/// Summary of a page as rendered, with links resolved, shortcodes expanded and
+/// math converted, for listings. Falls back to the summary of the Djot as
+/// written if the page fails to render.
+pub fn render_summary(
+    source: &SourceFile,
+    config: &SiteConfig,
+    tera: &Tera,
+    links: &LinkIndex,
+    images: &ImageSet,
+) -> Summary {
+    let mut options = RenderOptions::new(source, config, tera, links, images);
+    options.quiet = true;
+    djot_to_html(source.body(), &options)
+        .map(|rendered| rendered.summary)
+        .unwrap_or_else(|_| source.summary.clone())
+}
+
+/// Counts the words of a document and cuts its summary at a top-level
+/// `{% more %}` comment, or at the end of the first paragraph. Heading sections
+/// don't count as nesting; the ones still open at the marker are closed.
+pub fn summarize(events: &[Event]) -> Summary {
+    let mut text = String::new();
+    let mut depth = 0;
+    let mut skip_depth = 0;
+    let mut sections = Vec::new();
+    let mut more = None;
+    let mut paragraph_start = None;
+    let mut first_paragraph = None;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(container @ Container::Section { .. }, _) => {
+                sections.push(container.clone());
+            }
+            Event::End(Container::Section { .. }) => {
+                sections.pop();
+            }
+            Event::Start(container, attributes) => {
+                if depth == 0 && more.is_none() && is_more_marker(attributes) {
+                    more = Some((i, sections.clone()));
+                }
+                if depth == 0 && *container == Container::Paragraph {
+                    paragraph_start = Some(i);
+                }
+                if links::is_literal(container) {
+                    skip_depth += 1;
+                }
+                if container.is_block() {
+                    text.push(' ');
+                }
+                depth += 1;
+            }
+            Event::End(container) => {
+                depth -= 1;
+                if links::is_literal(container) {
+                    skip_depth -= 1;
+                }
+                if container.is_block() {
+                    text.push(' ');
+                }
+                if depth == 0 && *container == Container::Paragraph && first_paragraph.is_none() {
+                    first_paragraph = paragraph_start.map(|start| start..i + 1);
+                }
+            }
+            Event::Attributes(attributes)
+                if depth == 0 && more.is_none() && is_more_marker(attributes) =>
+            {
+                more = Some((i, sections.clone()));
+            }
+            _ if skip_depth == 0 => {
+                if let Some(s) = plain_text(event) {
+                    text.push_str(s);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let html = match (more, first_paragraph) {
+        (Some((end, open)), _) => {
+            let closing = open.into_iter().rev().map(Event::End);
+            Some(render_to_string(
+                events[..end].iter().cloned().chain(closing),
+            ))
+        }
+        (None, Some(range)) => Some(render_to_string(events[range].iter().cloned())),
+        (None, None) => None,
+    };
+    Summary {
+        word_count: text.split_whitespace().count(),
+        html: html.map(|html| html.trim().to_string()),
+    }
+}
+
+fn is_more_marker(attributes: &Attributes) -> bool {
+    attributes
+        .iter()
+        .any(|(kind, value)| *kind == AttributeKind::Comment && value.to_string().trim() == "more")
+}
+
+/// Rewrites a link to a `.djot` source into the URL of the page it renders to.
+/// Links that cannot be resolved are reported and kept as written.
+fn resolve_source_link(options: &RenderOptions, destination: &str, line: usize) -> Result<String> {
+    let (path, fragment) = links::split_fragment(destination);
+
+    let Some(target) = options.links.resolve_path(options.source, path) else {
+        options.broken_link(line, format!("broken link to `{path}`"))?;
+        return Ok(destination.to_string());
+    };
+
+    if !target.published {
+        options.broken_link(line, format!("`{path}` links to an unpublished page"))?;
+    }
+
+    Ok(format!("{}{fragment}", target.url))
+}
+
+/// Plain-text equivalent of an inline event, used wherever rendered markup is
+/// not wanted.
+fn plain_text<'a>(event: &'a Event) -> Option<&'a str> {
+    match event {
+        Event::Str(s) => Some(s),
+        Event::Symbol(s) => Some(s),
+        Event::LeftSingleQuote | Event::RightSingleQuote => Some("'"),
+        Event::LeftDoubleQuote | Event::RightDoubleQuote => Some("\""),
+        Event::Ellipsis => Some("…"),
+        Event::EnDash => Some("–"),
+        Event::EmDash => Some("—"),
+        Event::NonBreakingSpace | Event::Softbreak | Event::Hardbreak => Some(" "),
+        _ => None,
+    }
+}
+
+fn push_raw_html<'s>(events: &mut Vec<Event<'s>>, html: String) {
+    let container = Container::RawInline { format: "html" };
+    events.push(Event::Start(container.clone(), Attributes::new()));
+    events.push(Event::Str(html.into()));
+    events.push(Event::End(container));
+}
+
+fn push_raw_block<'s>(events: &mut Vec<Event<'s>>, html: String) {
+    let container = Container::RawBlock { format: "html" };
+    events.push(Event::Start(container.clone(), Attributes::new()));
+    events.push(Event::Str(html.into()));
+    events.push(Event::End(container));
+}
+
+fn insert_toc_entry(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+    match entries.last_mut() {
+        Some(last) if last.level < entry.level => insert_toc_entry(&mut last.children, entry),
+        _ => entries.push(entry),
+    }
 }
 
 fn create_page(
-    title: String,
-    desc: Option<String>,
-    date: Option<NaiveDate>,
-    content: String,
+    source: &SourceFile,
+    rendered: RenderedDjot,
     inline_css: String,
     inline_js: String,
 ) -> Page {
+    let metadata = &source.metadata;
     Page {
-        title,
-        desc: desc.unwrap_or_default(),
-        date: date.map_or("".to_string(), |d| d.format("%Y-%m-%d").to_string()),
-        content,
+        title: source.title.clone(),
+        url: source.url.clone(),
+        desc: metadata.desc.clone().unwrap_or_default(),
+        date: metadata
+            .date
+            .map_or("".to_string(), |d| d.format("%Y-%m-%d").to_string()),
+        content: rendered.html,
         inline_css,
         inline_js,
         assets_path: "/assets".to_string(),
+        toc: rendered.toc,
+        has_math: rendered.has_math,
+        word_count: rendered.summary.word_count,
+        reading_time: functions::reading_time(rendered.summary.word_count),
+        summary: rendered.summary.html_or(metadata.desc.as_deref()),
+        backlinks: source.backlinks.clone(),
+        draft: !metadata.status,
+        extra: metadata.extra.clone(),
     }
 }
 
-fn get_dest_path(djot_path: &Path, src_dir: &Path, dist_dir: &Path) -> Result<PathBuf> {
+pub fn get_dest_path(djot_path: &Path, src_dir: &Path, dist_dir: &Path) -> Result<PathBuf> {
     let relative_path = djot_path
         .strip_prefix(src_dir)
         .with_context(|| format!("failed to strip prefix {src_dir:?} from {djot_path:?}"))?;
@@ -158,24 +743,23 @@ fn get_dest_path(djot_path: &Path, src_dir: &Path, dist_dir: &Path) -> Result<Pa
     Ok(dest_path)
 }
 
-fn render_html_page(page: &Page, dest_path: &Path, template_dir: &Path) -> Result<()> {
-    if !template_dir.exists() {
-        return Err(anyhow!(
-            "template directory does not exist: {}",
-            template_dir.display()
-        ));
-    }
-
-    let template_pattern = template_dir.join("**/*.html");
-    let tera = Tera::new(template_pattern.to_str().unwrap())?;
-
-    let mut context = TeraContext::new();
+fn render_html_page(page: &Page, dest_path: &Path, site: &Site, head_assets: &str) -> Result<()> {
+    let mut context = site.context.clone();
     context.insert("page", page);
 
-    let mut output_file = fs::File::create(dest_path)
-        .with_context(|| format!("failed to create output file {dest_path:?}"))?;
+    let mut html = site
+        .tera
+        .render("layout.html", &context)
+        .map_err(|e| anyhow!(templates::error_chain(&e)))?;
+    if !head_assets.is_empty() {
+        math::inject_assets(&mut html, head_assets);
+    }
+    if site.config.minify {
+        html = assets::minify_html(&html);
+    }
 
-    tera.render_to("layout.html", &context, &mut output_file)?;
+    fs::write(dest_path, html)
+        .with_context(|| format!("failed to create output file {dest_path:?}"))?;
 
     Ok(())
 }
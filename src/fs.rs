@@ -1,42 +1,231 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
-pub fn prepare_directories(dist_dir: &Path) -> Result<()> {
+use crate::config::{CopyMode, SiteConfig};
+
+/// Empties the output directory, refusing to when that could delete anything
+/// that isn't a previous build: a directory outside the workspace, one that
+/// holds the workspace or its sources, or a git checkout.
+pub fn prepare_directories(dist_dir: &Path, workspace_path: &Path, src_dir: &Path) -> Result<()> {
     if dist_dir.exists() {
+        let dist = dist_dir.canonicalize()?;
+        let workspace = workspace_path.canonicalize()?;
+        let refuse = |reason: &str| anyhow!("refusing to clear {dist_dir:?}: {reason}");
+        if !dist.starts_with(&workspace) {
+            return Err(refuse("it is outside the workspace"));
+        }
+        if workspace.starts_with(&dist) || src_dir.canonicalize()?.starts_with(&dist) {
+            return Err(refuse("it contains the workspace sources"));
+        }
+        if dist.join(".git").exists() {
+            return Err(refuse("it contains a .git directory"));
+        }
         fs::remove_dir_all(dist_dir).context("failed to clear destination directory")?;
     }
     fs::create_dir_all(dist_dir).context("failed to create destination directory")?;
     Ok(())
 }
 
-pub fn collect_djot_files(src_dir: &Path) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
-    for entry in WalkDir::new(src_dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file() && path.extension().is_some_and(|ext| ext == "djot") {
-            files.push(path.to_path_buf());
+/// Files that are never part of a site, on top of the workspace `.arrowignore`.
+const DEFAULT_IGNORES: [&str; 6] = [".DS_Store", "Thumbs.db", "*.swp", "*.swo", "*~", ".#*"];
+
+/// Walks source directories, skipping ignored files.
+pub struct Walker {
+    ignore: Gitignore,
+    follow_symlinks: bool,
+}
+
+impl Walker {
+    /// Reads `.arrowignore` (gitignore syntax) from the workspace root, if any.
+    pub fn new(workspace_path: &Path, config: &SiteConfig) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(workspace_path);
+        for pattern in DEFAULT_IGNORES {
+            builder.add_line(None, pattern)?;
+        }
+        let ignore_file = workspace_path.join(".arrowignore");
+        if ignore_file.exists()
+            && let Some(e) = builder.add(&ignore_file)
+        {
+            return Err(anyhow!("invalid ignore file {ignore_file:?}: {e}"));
+        }
+
+        Ok(Walker {
+            ignore: builder.build()?,
+            follow_symlinks: config.follow_symlinks,
+        })
+    }
+
+    /// Every file under `dir` that is not ignored.
+    pub fn files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in self.walk(dir) {
+            let entry = entry?;
+            if entry.path().is_file() {
+                files.push(entry.into_path());
+            }
         }
+        Ok(files)
+    }
+
+    fn walk(&self, dir: &Path) -> impl Iterator<Item = Result<DirEntry>> {
+        WalkDir::new(dir)
+            .follow_links(self.follow_symlinks)
+            .into_iter()
+            .filter_entry(|entry| {
+                !self
+                    .ignore
+                    .matched(entry.path(), entry.file_type().is_dir())
+                    .is_ignore()
+            })
+            .map(|entry| entry.map_err(|e| anyhow!("failed to read source directory: {e}")))
     }
+}
+
+pub fn collect_djot_files(src_dir: &Path, walker: &Walker) -> Result<Vec<PathBuf>> {
+    let mut files = walker.files(src_dir)?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "djot"));
     Ok(files)
 }
 
-pub fn copy_assets(src: &Path, dest: &Path) -> Result<()> {
+pub fn copy_assets(src: &Path, dest: &Path, walker: &Walker, mode: CopyMode) -> Result<()> {
     if !src.exists() {
         return Ok(());
     }
 
-    for entry in WalkDir::new(src).into_iter().filter_map(|e| e.ok()) {
+    for entry in walker.walk(src) {
+        let entry = entry?;
         let path = entry.path();
         let relative_path = path.strip_prefix(src).context("failed to strip prefix")?;
         let dest_path = dest.join(relative_path);
 
         if path.is_dir() {
             fs::create_dir_all(&dest_path).context("failed to create asset directory")?;
+        } else if is_sass(path) {
+            if !is_partial(path) {
+                compile_sass(path, &dest_path.with_extension("css"))?;
+            }
         } else if path.is_file() {
-            fs::copy(path, &dest_path).context("failed to copy asset file")?;
+            copy_file(path, &dest_path, mode)
+                .with_context(|| format!("failed to copy asset file {path:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the workspace `static/` directory verbatim to the root of `dist/`.
+pub fn copy_static(
+    static_dir: &Path,
+    dist_dir: &Path,
+    walker: &Walker,
+    mode: CopyMode,
+) -> Result<()> {
+    if !static_dir.exists() {
+        return Ok(());
+    }
+    copy_tree(static_dir, dist_dir, walker, mode, |_| true)
+}
+
+/// Copies non-Djot files that sit next to pages, such as
+/// `src/posts/trip/photo.jpg`, alongside the page output. `assets_dir` is
+/// handled by `copy_assets`. Files are left out when the nearest directory
+/// above them in `page_dirs` has no published page.
+pub fn copy_page_files(
+    src_dir: &Path,
+    assets_dir: &Path,
+    dist_dir: &Path,
+    walker: &Walker,
+    mode: CopyMode,
+    page_dirs: &HashMap<&Path, bool>,
+) -> Result<()> {
+    copy_tree(src_dir, dist_dir, walker, mode, |path| {
+        let unpublished = path
+            .ancestors()
+            .skip(1)
+            .find_map(|dir| page_dirs.get(dir))
+            .is_some_and(|published| !published);
+        !unpublished
+            && !path.starts_with(assets_dir)
+            && path.extension().is_none_or(|ext| ext != "djot")
+    })
+}
+
+fn copy_tree(
+    src: &Path,
+    dest: &Path,
+    walker: &Walker,
+    mode: CopyMode,
+    include: impl Fn(&Path) -> bool,
+) -> Result<()> {
+    for entry in walker.walk(src) {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file() || !include(path) {
+            continue;
+        }
+
+        let relative_path = path.strip_prefix(src).context("failed to strip prefix")?;
+        let dest_path = dest.join(relative_path);
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {parent:?}"))?;
         }
+        copy_file(path, &dest_path, mode).with_context(|| format!("failed to copy {path:?}"))?;
     }
     Ok(())
 }
+
+/// Writes `contents` to `dest`, replacing any file there.
+pub fn replace_file(dest: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    remove_existing(dest).with_context(|| format!("failed to replace {dest:?}"))?;
+    fs::write(dest, contents).with_context(|| format!("failed to write {dest:?}"))
+}
+
+/// Output files are removed rather than overwritten, since they may be hard
+/// links to source or theme files that writing through would change.
+fn remove_existing(dest: &Path) -> std::io::Result<()> {
+    match fs::remove_file(dest) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Links fall back to a plain copy where the file system does not support them.
+fn copy_file(src: &Path, dest: &Path, mode: CopyMode) -> std::io::Result<()> {
+    remove_existing(dest)?;
+    match mode {
+        CopyMode::Copy => fs::copy(src, dest).map(|_| ()),
+        CopyMode::Hardlink => fs::hard_link(src, dest).or_else(|_| fs::copy(src, dest).map(|_| ())),
+        CopyMode::Reflink => reflink_copy::reflink_or_copy(src, dest).map(|_| ()),
+    }
+}
+
+fn is_sass(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext == "scss" || ext == "sass")
+}
+
+/// Partials (`_name.scss`) are only compiled as part of the files importing them.
+fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('_'))
+}
+
+/// Compiles a stylesheet next to the other assets. Syntax errors are reported
+/// with the file and line they occur on.
+fn compile_sass(path: &Path, dest_path: &Path) -> Result<()> {
+    let css = grass::from_path(path, &grass::Options::default()).map_err(|e| match e.kind() {
+        grass::ErrorKind::ParseError { message, loc, .. } => {
+            let file = loc.file.name();
+            let file = file.strip_prefix("./").unwrap_or(file);
+            anyhow!("{file}:{}: {message}", loc.begin.line + 1)
+        }
+        kind => anyhow!("failed to compile {path:?}: {kind:?}"),
+    })?;
+    replace_file(dest_path, css)
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Locale, NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use tera::{Tera, Value};
+
+use crate::site::SourceFile;
+
+/// Reading speed used for `reading_time`, in words per minute.
+const WORDS_PER_MINUTE: usize = 200;
+
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// A published page as returned by `get_page` and `get_section`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageRef {
+    /// Source path relative to `src`, e.g. `posts/trip.djot`.
+    pub path: String,
+    pub title: String,
+    pub desc: String,
+    pub date: String,
+    pub url: String,
+    pub word_count: usize,
+    pub reading_time: usize,
+    pub summary: String,
+    pub draft: bool,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct Section<'a> {
+    path: &'a str,
+    /// `index.djot` of the directory, if published.
+    index: Option<&'a PageRef>,
+    /// The other pages of the directory, newest first.
+    pages: Vec<&'a PageRef>,
+}
+
+/// Minutes needed to read `words` words, at least one.
+pub fn reading_time(words: usize) -> usize {
+    words.div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+/// Registers the filters that don't depend on the site's pages.
+pub fn register_filters(tera: &mut Tera) {
+    tera.register_filter("djotify", djotify);
+    tera.register_filter("markdownify", djotify);
+    tera.register_filter("reading_time", reading_time_filter);
+    tera.register_filter("date_format", date_format);
+}
+
+/// Registers `get_page`, `get_section` and `url_for`, which look up the
+/// published pages of the site.
+pub fn register_page_functions(tera: &mut Tera, sources: &[SourceFile]) {
+    let pages: Arc<Vec<PageRef>> = Arc::new(
+        sources
+            .iter()
+            .filter(|source| source.published)
+            .map(|source| PageRef {
+                path: source.relative.to_string_lossy().replace('\\', "/"),
+                title: source.title.clone(),
+                desc: source.metadata.desc.clone().unwrap_or_default(),
+                date: source
+                    .metadata
+                    .date
+                    .map_or(String::new(), |d| d.format("%Y-%m-%d").to_string()),
+                url: source.url.clone(),
+                word_count: source.summary.word_count,
+                reading_time: reading_time(source.summary.word_count),
+                summary: source.summary.html_or(source.metadata.desc.as_deref()),
+                draft: !source.metadata.status,
+                extra: source.metadata.extra.clone(),
+            })
+            .collect(),
+    );
+
+    let all = pages.clone();
+    tera.register_function("get_page", move |args: &HashMap<String, Value>| {
+        let page = find_page(&all, args, "get_page")?;
+        tera::to_value(page).map_err(tera::Error::from)
+    });
+
+    let all = pages.clone();
+    tera.register_function("url_for", move |args: &HashMap<String, Value>| {
+        let page = find_page(&all, args, "url_for")?;
+        Ok(Value::String(page.url.clone()))
+    });
+
+    tera.register_function("get_section", move |args: &HashMap<String, Value>| {
+        let path = string_arg(args, "path", "get_section")?;
+        let path = path.trim_matches('/');
+
+        let mut section = Section {
+            path,
+            index: None,
+            pages: Vec::new(),
+        };
+        for page in pages.iter() {
+            let page_path = Path::new(&page.path);
+            let dir = page_path.parent().unwrap_or(Path::new(""));
+            if dir != Path::new(path) {
+                continue;
+            }
+            if page_path.file_stem().is_some_and(|stem| stem == "index") {
+                section.index = Some(page);
+            } else {
+                section.pages.push(page);
+            }
+        }
+        section
+            .pages
+            .sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+
+        if section.index.is_none() && section.pages.is_empty() {
+            return Err(tera::Error::msg(format!(
+                "get_section() found no published pages in `{path}`"
+            )));
+        }
+        tera::to_value(section).map_err(tera::Error::from)
+    });
+}
+
+fn string_arg<'a>(
+    args: &'a HashMap<String, Value>,
+    name: &str,
+    function: &str,
+) -> tera::Result<&'a str> {
+    args.get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| tera::Error::msg(format!("{function}() requires a `{name}` argument")))
+}
+
+fn find_page<'a>(
+    pages: &'a [PageRef],
+    args: &HashMap<String, Value>,
+    function: &str,
+) -> tera::Result<&'a PageRef> {
+    let path = string_arg(args, "path", function)?;
+    let path = path.trim_start_matches('/');
+    pages
+        .iter()
+        .find(|page| page.path == path)
+        .ok_or_else(|| tera::Error::msg(format!("{function}() found no published page `{path}`")))
+}
+
+/// Renders a Djot snippet, such as a front matter field, to HTML.
+fn djotify(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = tera::try_get_value!("djotify", "value", String, value);
+    let html = jotdown::html::render_to_string(jotdown::Parser::new(&text));
+    Ok(Value::String(html))
+}
+
+fn reading_time_filter(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = tera::try_get_value!("reading_time", "value", String, value);
+    let words = TAG.replace_all(&text, " ").split_whitespace().count();
+    Ok(Value::from(reading_time(words)))
+}
+
+/// Formats a `YYYY-MM-DD` date or an RFC 3339 timestamp with a chrono format
+/// string, in the given `locale` (e.g. `fr_FR`, default `en_US`).
+fn date_format(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = tera::try_get_value!("date_format", "value", String, value);
+    let format = args
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("%Y-%m-%d");
+    let locale = match args.get("locale").and_then(|v| v.as_str()) {
+        Some(name) => Locale::try_from(name)
+            .map_err(|_| tera::Error::msg(format!("date_format: unknown locale `{name}`")))?,
+        None => Locale::en_US,
+    };
+
+    let mut formatted = String::new();
+    // Writing fails instead of panicking on an invalid format string.
+    let written = if let Ok(date) = NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+        write!(formatted, "{}", date.format_localized(format, locale))
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(&text) {
+        write!(formatted, "{}", time.format_localized(format, locale))
+    } else if let Ok(time) = NaiveDateTime::parse_from_str(&text, "%Y-%m-%dT%H:%M:%S") {
+        write!(
+            formatted,
+            "{}",
+            time.and_utc().format_localized(format, locale)
+        )
+    } else {
+        return Err(tera::Error::msg(format!(
+            "date_format: `{text}` is not a date"
+        )));
+    };
+    written.map_err(|_| tera::Error::msg(format!("date_format: invalid format `{format}`")))?;
+    Ok(Value::String(formatted))
+}
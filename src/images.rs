@@ -0,0 +1,377 @@
+use anyhow::{Context, Result};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ImageConfig, ImageOutput};
+use crate::fs::{Walker, replace_file};
+
+/// Processed images keyed by the URL of the original, e.g. `/assets/cat.jpg`.
+pub type ImageSet = HashMap<String, ProcessedImage>;
+
+/// A responsive image as exposed to Djot output and the `image()` template function.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessedImage {
+    pub src: String,
+    pub width: u32,
+    pub height: u32,
+    /// Candidates in the original format, as a `srcset` value.
+    pub srcset: String,
+    /// Candidates in the additional formats.
+    pub sources: Vec<ImageSource>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageSource {
+    #[serde(rename = "type")]
+    pub mime: String,
+    pub srcset: String,
+}
+
+/// Output formats an image is encoded to.
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Original(ImageFormat),
+    Extra(ImageOutput),
+}
+
+impl Encoding {
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Original(format) => format.extensions_str()[0],
+            Encoding::Extra(ImageOutput::Webp) => "webp",
+            Encoding::Extra(ImageOutput::Avif) => "avif",
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            Encoding::Original(format) => format.to_mime_type(),
+            Encoding::Extra(ImageOutput::Webp) => "image/webp",
+            Encoding::Extra(ImageOutput::Avif) => "image/avif",
+        }
+    }
+}
+
+/// Resizes and re-encodes every JPEG, PNG and WebP image under `assets_dir`,
+/// writing the variants next to the copies in `dest_dir`. Encoded files are kept
+/// in `cache_dir` so unchanged images are not processed again on the next build;
+/// entries of images that were removed or changed are deleted.
+pub fn process_images(
+    config: &ImageConfig,
+    assets_dir: &Path,
+    dest_dir: &Path,
+    cache_dir: &Path,
+    walker: &Walker,
+) -> Result<ImageSet> {
+    if !assets_dir.exists() {
+        return Ok(ImageSet::new());
+    }
+
+    fs::create_dir_all(cache_dir)
+        .with_context(|| format!("failed to create image cache {cache_dir:?}"))?;
+
+    let mut images = walker.files(assets_dir)?;
+    images.retain(|path| {
+        path.extension().is_some_and(|ext| {
+            matches!(
+                ext.to_string_lossy().to_lowercase().as_str(),
+                "jpg" | "jpeg" | "png" | "webp"
+            )
+        })
+    });
+
+    let processed: Vec<(String, ProcessedImage, String)> = images
+        .par_iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(assets_dir).ok()?;
+            match process_image(config, path, relative, dest_dir, cache_dir) {
+                Ok((image, source_hash)) => Some((asset_url(relative), image, source_hash)),
+                Err(e) => {
+                    eprintln!("warning: failed to process image {path:?}: {e:#}");
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let hashes: HashSet<&str> = processed.iter().map(|(_, _, h)| h.as_str()).collect();
+    prune_cache(cache_dir, &hashes)?;
+
+    Ok(processed
+        .into_iter()
+        .map(|(url, image, _)| (url, image))
+        .collect())
+}
+
+/// Deletes cached variants whose source hash, the part of the file name before
+/// the first `-`, is not in `hashes`.
+fn prune_cache(cache_dir: &Path, hashes: &HashSet<&str>) -> Result<()> {
+    for entry in fs::read_dir(cache_dir).context("failed to read image cache")? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let source_hash = name.split('-').next().unwrap_or_default();
+        if !hashes.contains(source_hash) {
+            fs::remove_file(&path).with_context(|| format!("failed to remove {path:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn process_image(
+    config: &ImageConfig,
+    path: &Path,
+    relative: &Path,
+    dest_dir: &Path,
+    cache_dir: &Path,
+) -> Result<(ProcessedImage, String)> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    let reader = ImageReader::new(Cursor::new(&bytes)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .context("could not determine image format")?;
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut original = DynamicImage::from_decoder(decoder)?;
+    original.apply_orientation(orientation);
+
+    let (width, height) = (original.width(), original.height());
+    let source_hash = format!("{:x}", Sha256::digest(&bytes));
+
+    let mut widths: Vec<u32> = config
+        .widths
+        .iter()
+        .copied()
+        .filter(|w| *w > 0 && *w < width)
+        .collect();
+    widths.sort_unstable();
+    widths.dedup();
+
+    // A WebP original already covers the `webp` format.
+    let mut encodings = vec![Encoding::Original(format)];
+    encodings.extend(
+        config
+            .formats
+            .iter()
+            .map(|f| Encoding::Extra(*f))
+            .filter(|encoding| encoding.mime() != format.to_mime_type()),
+    );
+
+    // Metadata is cut out of upright JPEGs as is; other JPEGs and PNGs are
+    // re-encoded, and WebP originals are copied untouched.
+    let upright = orientation == Orientation::NoTransforms;
+    let strip_in_place = config.strip_exif && format == ImageFormat::Jpeg && upright;
+    let reencode_original = config.strip_exif
+        && (format == ImageFormat::Png || (format == ImageFormat::Jpeg && !upright));
+
+    let stem = relative
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let dest_parent = dest_dir.join(relative.parent().unwrap_or(Path::new("")));
+    fs::create_dir_all(&dest_parent)?;
+
+    let mut candidates: Vec<(Encoding, Vec<String>)> = Vec::new();
+    for encoding in encodings {
+        let mut srcset = Vec::new();
+        for target in widths.iter().copied().chain([width]) {
+            let full_size = target == width;
+            let keep_original = full_size && encoding == Encoding::Original(format);
+            let file_name = if keep_original {
+                // keeps the extension as written, e.g. `.jpeg`
+                relative
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            } else if full_size {
+                format!("{stem}.{}", encoding.extension())
+            } else {
+                format!("{stem}-{target}w.{}", encoding.extension())
+            };
+
+            if keep_original && strip_in_place {
+                replace_file(&dest_parent.join(&file_name), strip_jpeg_metadata(&bytes)?)?;
+            } else if !keep_original || reencode_original {
+                let key = format!(
+                    "{source_hash}-{target}-{}.{}",
+                    config.quality,
+                    encoding.extension()
+                );
+                let cached = cache_dir.join(key);
+                if !cached.exists() {
+                    let resized = if full_size {
+                        original.clone()
+                    } else {
+                        original.resize(target, u32::MAX, FilterType::Lanczos3)
+                    };
+                    let encoded = encode(&resized, encoding, config.quality)?;
+                    fs::write(&cached, encoded)
+                        .with_context(|| format!("failed to write {cached:?}"))?;
+                }
+                let encoded =
+                    fs::read(&cached).with_context(|| format!("failed to read {cached:?}"))?;
+                replace_file(&dest_parent.join(&file_name), encoded)?;
+            }
+
+            let url = asset_url(&relative.with_file_name(&file_name));
+            srcset.push(format!("{url} {target}w"));
+        }
+        candidates.push((encoding, srcset));
+    }
+
+    let (_, original_srcset) = candidates.remove(0);
+    let sources: Vec<ImageSource> = candidates
+        .into_iter()
+        .map(|(encoding, srcset)| ImageSource {
+            mime: encoding.mime().to_string(),
+            srcset: srcset.join(", "),
+        })
+        .collect();
+
+    let image = ProcessedImage {
+        src: asset_url(relative),
+        width,
+        height,
+        srcset: original_srcset.join(", "),
+        sources,
+    };
+    Ok((image, source_hash))
+}
+
+fn encode(image: &DynamicImage, encoding: Encoding, quality: u8) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match encoding {
+        Encoding::Original(ImageFormat::Jpeg) => {
+            let encoder = JpegEncoder::new_with_quality(&mut bytes, quality);
+            image.to_rgb8().write_with_encoder(encoder)?;
+        }
+        Encoding::Original(ImageFormat::WebP) | Encoding::Extra(ImageOutput::Webp) => {
+            let rgba = image.to_rgba8();
+            let encoder = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height());
+            bytes.extend_from_slice(&encoder.encode(f32::from(quality)));
+        }
+        Encoding::Original(format) => image.write_to(&mut Cursor::new(&mut bytes), format)?,
+        Encoding::Extra(ImageOutput::Avif) => {
+            let encoder = AvifEncoder::new_with_speed_quality(&mut bytes, 8, quality);
+            image.to_rgba8().write_with_encoder(encoder)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Removes the EXIF, XMP and IPTC segments and comments of a JPEG file
+/// without decoding it, so the image data stays exactly as it was.
+fn strip_jpeg_metadata(bytes: &[u8]) -> Result<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        anyhow::bail!("not a JPEG file");
+    }
+    let mut stripped = bytes[..2].to_vec();
+    let mut pos = 2;
+    loop {
+        let (Some(0xFF), Some(&marker)) = (bytes.get(pos), bytes.get(pos + 1)) else {
+            anyhow::bail!("malformed JPEG segment at byte {pos}");
+        };
+        match marker {
+            // fill byte before a marker
+            0xFF => pos += 1,
+            // start of scan: the rest is image data
+            0xDA => {
+                stripped.extend_from_slice(&bytes[pos..]);
+                return Ok(stripped);
+            }
+            _ => {
+                let length = bytes
+                    .get(pos + 2..pos + 4)
+                    .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+                    .context("truncated JPEG segment")?;
+                let end = pos + 2 + length;
+                let segment = bytes.get(pos..end).context("truncated JPEG segment")?;
+                // APP1 holds EXIF and XMP, APP13 IPTC, and COM comments
+                if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+                    stripped.extend_from_slice(segment);
+                }
+                pos = end;
+            }
+        }
+    }
+}
+
+fn asset_url(relative: &Path) -> String {
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    format!("/assets/{}", parts.join("/"))
+}
+
+/// HTML for a Djot image that has responsive variants: a plain `img` with
+/// `srcset`, wrapped in a `picture` when additional formats exist.
+pub fn image_html(image: &ProcessedImage, alt: &str, attributes: &str, sizes: &str) -> String {
+    let sizes_attribute = if sizes.is_empty() {
+        String::new()
+    } else {
+        format!(" sizes=\"{}\"", tera::escape_html(sizes))
+    };
+
+    let img = format!(
+        "<img alt=\"{}\" src=\"{}\" srcset=\"{}\"{sizes_attribute} width=\"{}\" height=\"{}\"{attributes}>",
+        tera::escape_html(alt),
+        image.src,
+        image.srcset,
+        image.width,
+        image.height,
+    );
+
+    if image.sources.is_empty() {
+        return img;
+    }
+
+    let mut html = String::from("<picture>");
+    for source in &image.sources {
+        html.push_str(&format!(
+            "<source type=\"{}\" srcset=\"{}\"{sizes_attribute}>",
+            source.mime, source.srcset
+        ));
+    }
+    html.push_str(&img);
+    html.push_str("</picture>");
+    html
+}
+
+/// Registers `image(path=...)`, returning the processed variants of an image so
+/// layouts can build their own markup.
+pub fn register_function(tera: &mut tera::Tera, images: std::sync::Arc<ImageSet>) {
+    tera.register_function(
+        "image",
+        move |args: &HashMap<String, tera::Value>| -> tera::Result<tera::Value> {
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| tera::Error::msg("image() requires a `path` argument"))?;
+            let key = if path.starts_with("/assets/") {
+                path.to_string()
+            } else {
+                format!("/assets/{}", path.trim_start_matches('/'))
+            };
+            let image = images
+                .get(&key)
+                .ok_or_else(|| tera::Error::msg(format!("image() found no image `{path}`")))?;
+            tera::to_value(image).map_err(tera::Error::from)
+        },
+    );
+}
+
+/// Directory processed images are cached in, kept between builds.
+pub fn cache_dir(workspace_path: &Path) -> PathBuf {
+    workspace_path.join(".arrow-cache").join("images")
+}
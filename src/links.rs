@@ -0,0 +1,253 @@
+use jotdown::{Container, Event, LinkType, Parser};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::site::SourceFile;
+
+/// A page that internal links can point to.
+pub struct LinkTarget {
+    /// Position of the page in the site's sources.
+    pub id: usize,
+    pub title: String,
+    pub url: String,
+    pub published: bool,
+}
+
+/// Lookup table from source paths and titles to the pages they render to.
+pub struct LinkIndex {
+    targets: Vec<LinkTarget>,
+    by_path: HashMap<PathBuf, usize>,
+    by_title: HashMap<String, usize>,
+    by_stem: HashMap<String, usize>,
+}
+
+/// A link to another page as exposed to templates, e.g. in `page.backlinks`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageLink {
+    pub title: String,
+    pub url: String,
+}
+
+/// A part of a text run: either plain text or a `[[target]]`/`[[target|label]]`.
+#[derive(Debug, PartialEq)]
+pub enum TextPiece<'a> {
+    Text(&'a str),
+    Wiki {
+        target: &'a str,
+        label: Option<&'a str>,
+        offset: usize,
+    },
+}
+
+impl LinkIndex {
+    pub fn new(sources: &[SourceFile]) -> Self {
+        let mut index = LinkIndex {
+            targets: Vec::new(),
+            by_path: HashMap::new(),
+            by_title: HashMap::new(),
+            by_stem: HashMap::new(),
+        };
+
+        for (i, source) in sources.iter().enumerate() {
+            index.by_path.insert(source.relative.clone(), i);
+            index
+                .by_title
+                .entry(source.title.to_lowercase())
+                .or_insert(i);
+            if let Some(stem) = source.relative.file_stem() {
+                index
+                    .by_stem
+                    .entry(stem.to_string_lossy().to_lowercase())
+                    .or_insert(i);
+            }
+            index.targets.push(LinkTarget {
+                id: i,
+                title: source.title.clone(),
+                url: source.url.clone(),
+                published: source.published,
+            });
+        }
+
+        index
+    }
+
+    /// Resolves a `.djot` link destination written in `from`. Relative paths start
+    /// at the directory of `from`, paths starting with `/` at the `src` directory.
+    pub fn resolve_path(&self, from: &Path, target: &str) -> Option<&LinkTarget> {
+        let target_path = Path::new(target);
+        let joined = if target_path.has_root() {
+            target_path
+                .strip_prefix("/")
+                .unwrap_or(target_path)
+                .to_path_buf()
+        } else {
+            from.parent().unwrap_or(Path::new("")).join(target_path)
+        };
+
+        let index = self.by_path.get(&normalize(&joined)?)?;
+        Some(&self.targets[*index])
+    }
+
+    /// Resolves the target of a wiki link, trying in order a source path next to
+    /// `from`, a source path from the `src` root, a page title and a file name.
+    /// Returns whether the match was by path.
+    pub fn resolve_wiki(&self, from: &Path, target: &str) -> Option<(&LinkTarget, bool)> {
+        let target = target.trim();
+        let with_extension = if target.ends_with(".djot") {
+            target.to_string()
+        } else {
+            format!("{target}.djot")
+        };
+
+        let by_path = self
+            .resolve_path(from, &with_extension)
+            .or_else(|| self.resolve_path(Path::new(""), &with_extension));
+        if let Some(found) = by_path {
+            return Some((found, true));
+        }
+
+        let key = target.to_lowercase();
+        let index = self.by_title.get(&key).or_else(|| self.by_stem.get(&key))?;
+        Some((&self.targets[*index], false))
+    }
+
+    /// Ids of the pages a source links to, in order of first appearance. Links that
+    /// do not resolve are skipped here; they are reported when the page renders.
+    pub fn outgoing(&self, source: &SourceFile) -> Vec<usize> {
+        let mut targets = Vec::new();
+        let mut link_depth = 0;
+        let mut literal_depth = 0;
+
+        for event in Parser::new(source.body()) {
+            let found = match &event {
+                Event::Start(Container::Link(destination, kind), _) => {
+                    link_depth += 1;
+                    if matches!(kind, LinkType::Span(_)) && is_source_link(destination) {
+                        let (path, _) = split_fragment(destination);
+                        self.resolve_path(&source.relative, path)
+                            .into_iter()
+                            .collect()
+                    } else {
+                        Vec::new()
+                    }
+                }
+                Event::End(Container::Link(..)) => {
+                    link_depth -= 1;
+                    Vec::new()
+                }
+                Event::Start(container, _) if is_literal(container) => {
+                    literal_depth += 1;
+                    Vec::new()
+                }
+                Event::End(container) if is_literal(container) => {
+                    literal_depth -= 1;
+                    Vec::new()
+                }
+                Event::Str(text) if link_depth == 0 && literal_depth == 0 => split_wiki_links(text)
+                    .into_iter()
+                    .filter_map(|piece| match piece {
+                        TextPiece::Wiki { target, .. } => {
+                            self.resolve_wiki(&source.relative, target)
+                        }
+                        TextPiece::Text(_) => None,
+                    })
+                    .map(|(target, _)| target)
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            for target in found {
+                if !targets.contains(&target.id) {
+                    targets.push(target.id);
+                }
+            }
+        }
+
+        targets
+    }
+}
+
+/// Containers whose text is shown verbatim and never holds links.
+pub fn is_literal(container: &Container) -> bool {
+    matches!(
+        container,
+        Container::Verbatim
+            | Container::CodeBlock { .. }
+            | Container::RawBlock { .. }
+            | Container::RawInline { .. }
+    )
+}
+
+/// Splits `path#fragment` into the path and the fragment including its `#`.
+pub fn split_fragment(destination: &str) -> (&str, &str) {
+    match destination.find('#') {
+        Some(index) => destination.split_at(index),
+        None => (destination, ""),
+    }
+}
+
+/// Whether a link destination points at a Djot source file rather than a URL.
+pub fn is_source_link(destination: &str) -> bool {
+    let path = destination.split('#').next().unwrap_or_default();
+    path.ends_with(".djot") && !destination.contains("://")
+}
+
+/// Splits text into plain runs and wiki links.
+pub fn split_wiki_links(text: &str) -> Vec<TextPiece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    let mut consumed = 0;
+
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + 2 + len];
+        if inner.trim().is_empty() || inner.contains('[') {
+            pieces.push(TextPiece::Text(&rest[..start + 2]));
+            consumed += start + 2;
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        if start > 0 {
+            pieces.push(TextPiece::Text(&rest[..start]));
+        }
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (inner, None),
+        };
+        pieces.push(TextPiece::Wiki {
+            target,
+            label,
+            offset: consumed + start,
+        });
+
+        let end = start + 2 + len + 2;
+        consumed += end;
+        rest = &rest[end..];
+    }
+
+    if !rest.is_empty() {
+        pieces.push(TextPiece::Text(rest));
+    }
+    pieces
+}
+
+/// Resolves `.` and `..` without touching the filesystem, refusing to leave the root.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(normalized)
+}
@@ -0,0 +1,182 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+use crate::djot;
+
+/// Type a front matter field must have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Bool,
+    Integer,
+    Number,
+    Date,
+    List,
+    Map,
+    Any,
+}
+
+/// A custom front matter field declared under `fields` in `site.conf`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FieldSpec {
+    #[serde(rename = "type", default = "default_field_type")]
+    pub kind: FieldType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_field_type() -> FieldType {
+    FieldType::Any
+}
+
+/// Fields arrow itself understands.
+const BUILTIN_FIELDS: &[(&str, FieldType)] = &[
+    ("title", FieldType::String),
+    ("desc", FieldType::String),
+    ("date", FieldType::Date),
+    ("status", FieldType::Bool),
+    ("js", FieldType::String),
+    ("toc", FieldType::Bool),
+    ("menu", FieldType::Any),
+];
+
+/// A problem with the front matter of one file.
+pub struct Issue {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Validates the front matter of a source file against the built-in fields and
+/// the custom ones declared in the site config.
+pub fn lint_front_matter(content: &str, custom: &BTreeMap<String, FieldSpec>) -> Vec<Issue> {
+    let Some((start, end)) = djot::front_matter_range(content) else {
+        return missing_required(custom, &serde_yaml::Mapping::new(), 1);
+    };
+    let first_line = content[..start].matches('\n').count() + 1;
+
+    let fields = match serde_yaml::from_str::<Value>(&content[start..end]) {
+        Ok(Value::Mapping(fields)) => fields,
+        Ok(Value::Null) => serde_yaml::Mapping::new(),
+        Ok(_) => {
+            return vec![Issue {
+                line: first_line,
+                message: "front matter is not a mapping of fields".to_string(),
+            }];
+        }
+        Err(e) => {
+            let line = e
+                .location()
+                .map_or(first_line, |l| first_line + l.line() - 1);
+            return vec![Issue {
+                line,
+                message: format!("invalid front matter YAML: {e}"),
+            }];
+        }
+    };
+
+    let front_matter = &content[start..end];
+    let line_of = |key: &str| {
+        front_matter
+            .lines()
+            .position(|line| {
+                line.strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start().starts_with(':'))
+            })
+            .map_or(first_line, |index| first_line + index)
+    };
+
+    let mut issues = Vec::new();
+    for (key, value) in &fields {
+        let Some(key) = key.as_str() else {
+            issues.push(Issue {
+                line: first_line,
+                message: format!("field names must be strings, found {}", describe(key)),
+            });
+            continue;
+        };
+
+        let expected = BUILTIN_FIELDS
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, kind)| *kind)
+            .or_else(|| custom.get(key).map(|spec| spec.kind));
+
+        match expected {
+            None => issues.push(Issue {
+                line: line_of(key),
+                message: format!("unknown field `{key}`"),
+            }),
+            Some(kind) => {
+                if let Some(message) = check_type(kind, value) {
+                    issues.push(Issue {
+                        line: line_of(key),
+                        message: format!("field `{key}` {message}"),
+                    });
+                }
+            }
+        }
+    }
+
+    issues.extend(missing_required(custom, &fields, first_line));
+    issues
+}
+
+fn missing_required(
+    custom: &BTreeMap<String, FieldSpec>,
+    fields: &serde_yaml::Mapping,
+    line: usize,
+) -> Vec<Issue> {
+    custom
+        .iter()
+        .filter(|(key, spec)| spec.required && !fields.contains_key(key.as_str()))
+        .map(|(key, _)| Issue {
+            line,
+            message: format!("missing required field `{key}`"),
+        })
+        .collect()
+}
+
+fn check_type(kind: FieldType, value: &Value) -> Option<String> {
+    let valid = match kind {
+        FieldType::Any => true,
+        FieldType::String => value.is_string(),
+        FieldType::Bool => value.is_bool(),
+        FieldType::Integer => value.is_i64() || value.is_u64(),
+        FieldType::Number => value.is_number(),
+        FieldType::List => value.is_sequence(),
+        FieldType::Map => value.is_mapping(),
+        FieldType::Date => {
+            return match value.as_str() {
+                Some(date) if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() => None,
+                Some(date) => Some(format!("has invalid date `{date}`, expected YYYY-MM-DD")),
+                None => Some(format!("must be a date, found {}", describe(value))),
+            };
+        }
+    };
+
+    if valid {
+        None
+    } else {
+        let expected = serde_yaml::to_string(&kind).unwrap_or_default();
+        Some(format!(
+            "must be of type {}, found {}",
+            expected.trim(),
+            describe(value)
+        ))
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "nothing".to_string(),
+        Value::Bool(b) => format!("bool `{b}`"),
+        Value::Number(n) => format!("number `{n}`"),
+        Value::String(s) => format!("string `{s}`"),
+        Value::Sequence(_) => "a list".to_string(),
+        Value::Mapping(_) => "a map".to_string(),
+        Value::Tagged(tagged) => format!("tagged value `{}`", tagged.tag),
+    }
+}
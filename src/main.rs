@@ -1,10 +1,23 @@
 use clap::{Arg, ArgAction, Command};
+use std::path::{Path, PathBuf};
 use std::process;
 
+mod assets;
+mod check;
 mod commands;
 mod config;
+mod data;
 mod djot;
 mod fs;
+mod functions;
+mod images;
+mod links;
+mod lint;
+mod math;
+mod menus;
+mod shortcodes;
+mod site;
+mod templates;
 
 #[tokio::main]
 async fn main() {
@@ -13,6 +26,22 @@ async fn main() {
         .about("a simple static site generator")
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .help("use this config file instead of the default (also ARROW_CONFIG)")
+                .global(true)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .help("override a setting, e.g. site.base_url=https://example.com")
+                .global(true)
+                .action(ArgAction::Append),
+        )
         .subcommand(
             Command::new("serve")
                 .about("start a local server and watch for changes")
@@ -24,6 +53,13 @@ async fn main() {
                         .help("specify the port to serve on")
                         .action(ArgAction::Set),
                 )
+                .arg(
+                    Arg::new("env")
+                        .long("env")
+                        .value_name("NAME")
+                        .help("environment from site.conf to use (default: dev)")
+                        .action(ArgAction::Set),
+                )
                 .arg(
                     Arg::new("entry")
                         .long("entry")
@@ -34,6 +70,24 @@ async fn main() {
                         .action(ArgAction::Set),
                 ),
         )
+        .subcommand(
+            Command::new("init")
+                .about("create a new workspace and register it in the config")
+                .arg(
+                    Arg::new("path")
+                        .value_name("PATH")
+                        .help("directory to create the workspace in")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .value_name("KEY")
+                        .help("workspace key to register (defaults to the directory name)")
+                        .action(ArgAction::Set),
+                ),
+        )
         .subcommand(
             Command::new("new")
                 .about("create a new entry in workspace")
@@ -62,6 +116,66 @@ async fn main() {
                         .action(ArgAction::Set),
                 ),
         )
+        .subcommand(
+            Command::new("publish")
+                .about("mark source djot files as published")
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .short('e')
+                        .value_name("ENTRY")
+                        .help("specify the workspace key (e.g., site, notes)")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .help("source files or glob patterns, relative to `src` if not found")
+                        .required(true)
+                        .num_args(1..)
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("date")
+                        .long("date")
+                        .help("also set the date to today")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("show the changes without writing them")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("unpublish")
+                .about("mark source djot files as drafts")
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .short('e')
+                        .value_name("ENTRY")
+                        .help("specify the workspace key (e.g., site, notes)")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .help("source files or glob patterns, relative to `src` if not found")
+                        .required(true)
+                        .num_args(1..)
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("show the changes without writing them")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .subcommand(
             Command::new("build")
                 .about("build the static source files")
@@ -74,10 +188,181 @@ async fn main() {
                         .help("specify the workspace key (e.g., site, notes)")
                         .required(true)
                         .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("check the output for broken links after building")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .short('o')
+                        .value_name("DIR")
+                        .help("build into this directory instead of the configured output")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("no-clean")
+                        .long("no-clean")
+                        .help("build over the existing output instead of clearing it")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("env")
+                        .long("env")
+                        .value_name("NAME")
+                        .help("environment from site.conf to use (default: prod)")
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("validate the front matter of all source djot files")
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .short('e')
+                        .value_name("ENTRY")
+                        .help("specify the workspace key (e.g., site, notes)")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("templates")
+                .about("work with the templates of a workspace")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("check")
+                        .about("compile and validate all templates without building")
+                        .arg(
+                            Arg::new("entry")
+                                .long("entry")
+                                .short('e')
+                                .value_name("ENTRY")
+                                .help("specify the workspace key (e.g., site, notes)")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("check the built site for broken links and missing assets")
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .short('e')
+                        .value_name("ENTRY")
+                        .help("specify the workspace key (e.g., site, notes)")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("external")
+                        .long("external")
+                        .help("also request external links")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("inspect the global config")
+                .subcommand_required(true)
+                .subcommand(Command::new("path").about("print the path of the config file"))
+                .subcommand(
+                    Command::new("show")
+                        .about("print the config")
+                        .arg(
+                            Arg::new("resolved")
+                                .long("resolved")
+                                .help("print every effective setting and where it comes from")
+                                .action(ArgAction::SetTrue),
+                        )
+                        .arg(
+                            Arg::new("entry")
+                                .long("entry")
+                                .short('e')
+                                .value_name("ENTRY")
+                                .help("with --resolved, also show the settings of this workspace")
+                                .requires("resolved")
+                                .action(ArgAction::Set),
+                        )
+                        .arg(
+                            Arg::new("env")
+                                .long("env")
+                                .value_name("NAME")
+                                .help("environment from site.conf to use (default: prod)")
+                                .requires("entry")
+                                .action(ArgAction::Set),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("workspace")
+                .about("manage the workspaces in the global config")
+                .after_help(
+                    "these commands rewrite the workspaces of the user config, keeping its \
+                     other settings but not comments",
+                )
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("register an existing directory as a workspace")
+                        .arg(
+                            Arg::new("name")
+                                .value_name("NAME")
+                                .help("workspace key (e.g., site, notes)")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        )
+                        .arg(
+                            Arg::new("path")
+                                .value_name("PATH")
+                                .help("directory of the workspace")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("unregister a workspace, leaving its files alone")
+                        .arg(
+                            Arg::new("name")
+                                .value_name("NAME")
+                                .help("workspace key (e.g., site, notes)")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ),
+                )
+                .subcommand(
+                    Command::new("set-default")
+                        .about("make a workspace the default")
+                        .arg(
+                            Arg::new("name")
+                                .value_name("NAME")
+                                .help("workspace key (e.g., site, notes)")
+                                .required(true)
+                                .action(ArgAction::Set),
+                        ),
                 ),
         )
         .get_matches();
 
+    if let Some(path) = matches.get_one::<String>("config") {
+        config::set_config_path(PathBuf::from(path));
+    }
+    let sets: Vec<String> = matches
+        .get_many::<String>("set")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    if let Err(e) = config::set_overrides(&sets) {
+        eprintln!("Error: {e:#}");
+        process::exit(1);
+    }
+
     let result = match matches.subcommand() {
         Some(("serve", sub_m)) => {
             let port = sub_m
@@ -86,7 +371,8 @@ async fn main() {
                 .unwrap_or(0);
 
             let entry = sub_m.get_one::<String>("entry").unwrap();
-            commands::serve_command(port, entry).await
+            let env = sub_m.get_one::<String>("env").map_or("dev", String::as_str);
+            commands::serve_command(port, entry, env).await
         }
         Some(("new", sub_m)) => {
             let entry = sub_m.get_one::<String>("entry").unwrap();
@@ -96,15 +382,78 @@ async fn main() {
             let entry = sub_m.get_one::<String>("entry").unwrap();
             commands::status_command(entry)
         }
+        Some((name @ ("publish" | "unpublish"), sub_m)) => {
+            let entry = sub_m.get_one::<String>("entry").unwrap();
+            let paths: Vec<String> = sub_m
+                .get_many::<String>("paths")
+                .unwrap()
+                .cloned()
+                .collect();
+            let set_date = name == "publish" && sub_m.get_flag("date");
+            let dry_run = sub_m.get_flag("dry-run");
+            commands::set_status_command(entry, &paths, name == "publish", set_date, dry_run)
+        }
         Some(("build", sub_m)) => {
             let entry = sub_m.get_one::<String>("entry").unwrap();
-            commands::build_command(entry).await
+            let out = sub_m.get_one::<String>("out").map(Path::new);
+            let clean = !sub_m.get_flag("no-clean");
+            let env = sub_m
+                .get_one::<String>("env")
+                .map_or("prod", String::as_str);
+            commands::build_command(entry, sub_m.get_flag("check"), env, out, clean).await
+        }
+        Some(("lint", sub_m)) => {
+            let entry = sub_m.get_one::<String>("entry").unwrap();
+            commands::lint_command(entry)
+        }
+        Some(("init", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").unwrap();
+            let name = sub_m.get_one::<String>("name").map(String::as_str);
+            commands::init_command(Path::new(path), name)
+        }
+        Some(("templates", sub_m)) => match sub_m.subcommand() {
+            Some(("check", sub_m)) => {
+                let entry = sub_m.get_one::<String>("entry").unwrap();
+                commands::templates_check_command(entry)
+            }
+            _ => unreachable!(),
+        },
+        Some(("check", sub_m)) => {
+            let entry = sub_m.get_one::<String>("entry").unwrap();
+            commands::check_command(entry, sub_m.get_flag("external")).await
         }
+        Some(("config", sub_m)) => match sub_m.subcommand() {
+            Some(("path", _)) => commands::config_path_command(),
+            Some(("show", sub_m)) => {
+                let entry = sub_m.get_one::<String>("entry").map(String::as_str);
+                let env = sub_m
+                    .get_one::<String>("env")
+                    .map_or("prod", String::as_str);
+                commands::config_show_command(sub_m.get_flag("resolved"), entry, env)
+            }
+            _ => unreachable!(),
+        },
+        Some(("workspace", sub_m)) => match sub_m.subcommand() {
+            Some(("add", sub_m)) => {
+                let name = sub_m.get_one::<String>("name").unwrap();
+                let path = sub_m.get_one::<String>("path").unwrap();
+                commands::workspace_add_command(name, Path::new(path))
+            }
+            Some(("remove", sub_m)) => {
+                let name = sub_m.get_one::<String>("name").unwrap();
+                commands::workspace_remove_command(name)
+            }
+            Some(("set-default", sub_m)) => {
+                let name = sub_m.get_one::<String>("name").unwrap();
+                commands::workspace_set_default_command(name)
+            }
+            _ => unreachable!(),
+        },
         _ => unreachable!(),
     };
 
     if let Err(e) = result {
-        eprintln!("Error: {e}");
+        eprintln!("Error: {e:#}");
         process::exit(1);
     }
 }
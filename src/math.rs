@@ -0,0 +1,44 @@
+use anyhow::{Result, anyhow};
+use latex2mathml::{DisplayStyle, latex_to_mathml};
+
+use crate::config::MathRenderer;
+
+const KATEX_ASSETS: &str = concat!(
+    r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@0.16.11/dist/katex.min.css">"#,
+    "\n",
+    r#"<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16.11/dist/katex.min.js"></script>"#,
+    "\n",
+    r#"<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16.11/dist/contrib/auto-render.min.js" onload="renderMathInElement(document.body)"></script>"#,
+    "\n",
+);
+
+const MATHJAX_ASSETS: &str = concat!(
+    r#"<script defer src="https://cdn.jsdelivr.net/npm/mathjax@3/es5/tex-chtml.js"></script>"#,
+    "\n",
+);
+
+pub fn to_mathml(latex: &str, display: bool) -> Result<String> {
+    let style = if display {
+        DisplayStyle::Block
+    } else {
+        DisplayStyle::Inline
+    };
+    latex_to_mathml(latex, style).map_err(|e| anyhow!("failed to render math `{latex}`: {e}"))
+}
+
+/// Tags that have to be added to a page containing math for the renderer to work.
+pub fn client_assets(renderer: MathRenderer) -> &'static str {
+    match renderer {
+        MathRenderer::Mathml => "",
+        MathRenderer::Katex => KATEX_ASSETS,
+        MathRenderer::Mathjax => MATHJAX_ASSETS,
+    }
+}
+
+/// Places `assets` right before `</head>`, or at the end of documents without one.
+pub fn inject_assets(html: &mut String, assets: &str) {
+    match html.find("</head>") {
+        Some(index) => html.insert_str(index, assets),
+        None => html.push_str(assets),
+    }
+}
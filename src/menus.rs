@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::site::SourceFile;
+
+/// Menus by name, each sorted by weight.
+pub type Menus = BTreeMap<String, Vec<MenuItem>>;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MenuItem {
+    pub name: String,
+    pub url: String,
+    /// Lighter items come first.
+    #[serde(default)]
+    pub weight: i32,
+}
+
+/// The `menu` front matter field: a menu name, a list of names, or a map of
+/// names to a weight and display name.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum MenuRef {
+    Name(String),
+    Names(Vec<String>),
+    Entries(BTreeMap<String, MenuEntry>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MenuEntry {
+    /// Defaults to the page title.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub weight: i32,
+}
+
+/// Merges the menus of the site config with the published pages that add
+/// themselves to a menu.
+pub fn build(configured: &Menus, sources: &[SourceFile]) -> Menus {
+    let mut menus = configured.clone();
+
+    for source in sources.iter().filter(|s| s.published) {
+        let entries: Vec<(String, MenuEntry)> = match &source.metadata.menu {
+            None => continue,
+            Some(MenuRef::Name(name)) => vec![(name.clone(), MenuEntry::default())],
+            Some(MenuRef::Names(names)) => names
+                .iter()
+                .map(|name| (name.clone(), MenuEntry::default()))
+                .collect(),
+            Some(MenuRef::Entries(entries)) => entries.clone().into_iter().collect(),
+        };
+
+        for (menu, entry) in entries {
+            menus.entry(menu).or_default().push(MenuItem {
+                name: entry.name.unwrap_or_else(|| source.title.clone()),
+                url: source.url.clone(),
+                weight: entry.weight,
+            });
+        }
+    }
+
+    for items in menus.values_mut() {
+        items.sort_by(|a, b| a.weight.cmp(&b.weight).then_with(|| a.name.cmp(&b.name)));
+    }
+    menus
+}
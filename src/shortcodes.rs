@@ -0,0 +1,90 @@
+use anyhow::{Result, anyhow};
+use jotdown::{Attributes, Container};
+use tera::{Context as TeraContext, Tera};
+
+use crate::templates;
+
+/// A shortcode whose container has been opened but not closed yet.
+pub struct OpenShortcode {
+    pub name: String,
+    pub args: TeraContext,
+    pub line: usize,
+    pub block: bool,
+    /// Index of the first event belonging to the body.
+    pub body_start: usize,
+}
+
+/// Template a shortcode is rendered with.
+fn template_name(name: &str) -> String {
+    format!("shortcodes/{name}.html")
+}
+
+/// Returns the shortcode invoked by a container: a div such as `::: figure`, or a
+/// span such as `[text]{.figure}`, provided `templates/shortcodes/figure.html`
+/// exists.
+pub fn lookup(
+    tera: &Tera,
+    container: &Container,
+    attributes: &Attributes,
+    line: usize,
+    body_start: usize,
+) -> Option<OpenShortcode> {
+    let exists = |name: &str| {
+        let template = template_name(name);
+        tera.get_template_names().any(|t| t == template)
+    };
+
+    let (name, block) = match container {
+        Container::Div { class } if exists(class) => (class.to_string(), true),
+        Container::Span => {
+            let classes = attributes
+                .unique_pairs()
+                .find(|(key, _)| *key == "class")
+                .map(|(_, value)| value.to_string())
+                .unwrap_or_default();
+            let name = classes.split_whitespace().find(|c| exists(c))?;
+            (name.to_string(), false)
+        }
+        _ => return None,
+    };
+
+    let mut args = TeraContext::new();
+    for (key, value) in attributes.unique_pairs() {
+        if key == "class" {
+            let rest: Vec<String> = value
+                .to_string()
+                .split_whitespace()
+                .filter(|c| *c != name)
+                .map(str::to_string)
+                .collect();
+            args.insert("class", &rest.join(" "));
+        } else {
+            args.insert(key, &value.to_string());
+        }
+    }
+
+    Some(OpenShortcode {
+        name,
+        args,
+        line,
+        block,
+        body_start,
+    })
+}
+
+/// Renders the shortcode template with its arguments and the already rendered
+/// body, available to the template as `body`.
+pub fn render(tera: &Tera, shortcode: OpenShortcode, body: &str, source: &str) -> Result<String> {
+    let mut context = shortcode.args;
+    context.insert("body", body);
+
+    tera.render(&template_name(&shortcode.name), &context)
+        .map_err(|e| {
+            anyhow!(
+                "{source}:{}: shortcode `{}` failed: {}",
+                shortcode.line,
+                shortcode.name,
+                templates::error_chain(&e)
+            )
+        })
+}
@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tera::{Context as TeraContext, Tera};
+
+use crate::config::SiteConfig;
+use crate::djot::{self, Metadata, Summary};
+use crate::functions;
+use crate::images::ImageSet;
+use crate::links::{LinkIndex, PageLink};
+use crate::menus;
+
+/// A Djot source file, read and split into front matter and body once per build.
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// Path relative to the `src` directory.
+    pub relative: PathBuf,
+    pub content: String,
+    body_start: usize,
+    pub metadata: Metadata,
+    /// Whether the page is built: its `status`, or any page with `drafts` on.
+    pub published: bool,
+    pub title: String,
+    /// Absolute URL of the rendered page, e.g. `/posts/trip.html`.
+    pub url: String,
+    /// Published pages linking to this one.
+    pub backlinks: Vec<PageLink>,
+    /// For `get_page` and `get_section`: taken from the Djot as written when
+    /// loaded, then from the rendered page once `Site::new` resolved links.
+    pub summary: Summary,
+}
+
+impl SourceFile {
+    pub fn load(path: &Path, src_dir: &Path, dist_dir: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("failed to read file {path:?}"))?;
+        let (metadata, body) = djot::parse_front_matter(&content)?;
+        let body_start = content.len() - body.len();
+
+        let relative = path
+            .strip_prefix(src_dir)
+            .with_context(|| format!("failed to strip prefix {src_dir:?} from {path:?}"))?
+            .to_path_buf();
+        let dest_path = djot::get_dest_path(path, src_dir, dist_dir)?;
+        let url = url_for(&dest_path, dist_dir);
+        let title = djot::get_default_title(path, metadata.title.clone());
+        let events: Vec<_> = jotdown::Parser::new(&body).collect();
+        let summary = djot::summarize(&events);
+
+        Ok(SourceFile {
+            path: path.to_path_buf(),
+            relative,
+            content,
+            body_start,
+            published: metadata.status,
+            metadata,
+            title,
+            url,
+            backlinks: Vec::new(),
+            summary,
+        })
+    }
+
+    /// The Djot content after the front matter.
+    pub fn body(&self) -> &str {
+        &self.content[self.body_start..]
+    }
+
+    /// Line of the source file that the body starts on.
+    pub fn body_line(&self) -> usize {
+        self.content[..self.body_start].matches('\n').count() + 1
+    }
+}
+
+/// Exposed to templates as `build`.
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    /// Start of the build, RFC 3339.
+    pub timestamp: String,
+    pub version: &'static str,
+    /// Environment from `--env`: `dev` when serving and `prod` when building by default.
+    pub env: String,
+}
+
+impl BuildInfo {
+    pub fn new(env: &str) -> Self {
+        BuildInfo {
+            timestamp: Local::now().to_rfc3339(),
+            version: env!("CARGO_PKG_VERSION"),
+            env: env.to_string(),
+        }
+    }
+}
+
+/// Everything a page needs to be rendered, shared by all pages of a build.
+pub struct Site {
+    pub src_dir: PathBuf,
+    pub dist_dir: PathBuf,
+    pub config: SiteConfig,
+    pub tera: Tera,
+    pub sources: Vec<SourceFile>,
+    pub links: LinkIndex,
+    pub images: Arc<ImageSet>,
+    /// Template variables shared by every page: `site`, `build`, `data` and `menus`.
+    pub context: TeraContext,
+    /// Links between published pages, as pairs of source ids.
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl Site {
+    /// Reads every source file up front so pages can refer to each other. Files
+    /// that cannot be read are reported and left out.
+    pub fn new(
+        src_dir: PathBuf,
+        dist_dir: PathBuf,
+        config: SiteConfig,
+        mut tera: Tera,
+        images: Arc<ImageSet>,
+        mut context: TeraContext,
+        files: &[PathBuf],
+    ) -> Self {
+        let mut sources = Vec::new();
+        for file in files {
+            match SourceFile::load(file, &src_dir, &dist_dir) {
+                Ok(mut source) => {
+                    source.published |= config.drafts;
+                    sources.push(source);
+                }
+                Err(e) => eprintln!("error processing file {file:?}: {e:#}"),
+            }
+        }
+        sources.sort_by(|a, b| a.relative.cmp(&b.relative));
+
+        let links = LinkIndex::new(&sources);
+
+        let mut edges = Vec::new();
+        for (from, source) in sources.iter().enumerate() {
+            if !source.published {
+                continue;
+            }
+            for to in links.outgoing(source) {
+                if to != from && sources[to].published {
+                    edges.push((from, to));
+                }
+            }
+        }
+
+        for &(from, to) in &edges {
+            let link = PageLink {
+                title: sources[from].title.clone(),
+                url: sources[from].url.clone(),
+            };
+            sources[to].backlinks.push(link);
+        }
+
+        // Shortcodes may list pages too, so they see the summaries as written
+        // while the rendered ones are computed.
+        functions::register_page_functions(&mut tera, &sources);
+        let summaries: Vec<Option<Summary>> = sources
+            .par_iter()
+            .map(|source| {
+                source
+                    .published
+                    .then(|| djot::render_summary(source, &config, &tera, &links, &images))
+            })
+            .collect();
+        for (source, summary) in sources.iter_mut().zip(summaries) {
+            if let Some(summary) = summary {
+                source.summary = summary;
+            }
+        }
+        functions::register_page_functions(&mut tera, &sources);
+        context.insert("site", &config.site);
+        context.insert("menus", &menus::build(&config.menus, &sources));
+
+        Site {
+            src_dir,
+            dist_dir,
+            config,
+            tera,
+            sources,
+            links,
+            images,
+            context,
+            edges,
+        }
+    }
+
+    /// Directories below `src` that hold pages, and whether any of those pages is
+    /// published. Files bundled with pages that are all unpublished are not copied.
+    pub fn page_dirs(&self) -> HashMap<&Path, bool> {
+        let mut dirs: HashMap<&Path, bool> = HashMap::new();
+        for source in &self.sources {
+            if let Some(dir) = source.path.parent()
+                && dir != self.src_dir
+            {
+                *dirs.entry(dir).or_default() |= source.published;
+            }
+        }
+        dirs
+    }
+
+    /// Writes `graph.json` with the published pages as nodes and the links between
+    /// them as edges, for client-side graph views.
+    pub fn write_graph(&self) -> Result<()> {
+        #[derive(Serialize)]
+        struct Node<'a> {
+            id: &'a str,
+            title: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct Edge<'a> {
+            source: &'a str,
+            target: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct Graph<'a> {
+            nodes: Vec<Node<'a>>,
+            edges: Vec<Edge<'a>>,
+        }
+
+        let graph = Graph {
+            nodes: self
+                .sources
+                .iter()
+                .filter(|source| source.published)
+                .map(|source| Node {
+                    id: &source.url,
+                    title: &source.title,
+                })
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .map(|&(from, to)| Edge {
+                    source: &self.sources[from].url,
+                    target: &self.sources[to].url,
+                })
+                .collect(),
+        };
+
+        let graph_path = self.dist_dir.join("graph.json");
+        let json = serde_json::to_string_pretty(&graph)?;
+        fs::write(&graph_path, json).with_context(|| format!("failed to write {graph_path:?}"))?;
+
+        Ok(())
+    }
+}
+
+fn url_for(dest_path: &Path, dist_dir: &Path) -> String {
+    let relative = dest_path.strip_prefix(dist_dir).unwrap_or(dest_path);
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    format!("/{}", parts.join("/"))
+}
@@ -0,0 +1,146 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tera::Tera;
+use tera::ast::Node;
+
+use crate::functions;
+
+/// Layout used when the workspace has no `templates/layout.html`.
+const FALLBACK_LAYOUT: &str = include_str!("scaffold/fallback.html");
+
+/// Loads every `.html` file of `template_dirs`, named by its path relative to
+/// its directory. Later directories override templates of the same name in
+/// earlier ones, so a workspace can replace single templates of its theme.
+pub fn load(template_dirs: &[PathBuf]) -> Result<Tera> {
+    let mut files = BTreeMap::new();
+    for dir in template_dirs.iter().filter(|dir| dir.exists()) {
+        let pattern = dir.join("**/*.html");
+        let paths = glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| anyhow!("invalid template directory {dir:?}: {e}"))?;
+        for path in paths {
+            let path = path.map_err(|e| anyhow!("failed to read templates: {e}"))?;
+            if !path.is_file() {
+                continue;
+            }
+            let name = path
+                .strip_prefix(dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.insert(name, path);
+        }
+    }
+
+    let mut tera = Tera::default();
+    tera.add_template_files(files.iter().map(|(name, path)| (path, Some(name))))
+        .map_err(|e| anyhow!(load_error(&e, template_dirs)))?;
+
+    if tera.get_template("layout.html").is_err() {
+        println!("no templates/layout.html found, using the built-in layout.");
+        tera.add_raw_template("layout.html", FALLBACK_LAYOUT)
+            .map_err(|e| anyhow!(error_chain(&e)))?;
+    }
+    validate(&tera)?;
+    functions::register_filters(&mut tera);
+    Ok(tera)
+}
+
+/// Checks what Tera leaves to render time: that every template included is
+/// loaded.
+fn validate(tera: &Tera) -> Result<()> {
+    for name in tera.get_template_names() {
+        let template = tera.get_template(name)?;
+        let mut includes = Vec::new();
+        collect_includes(&template.ast, &mut includes);
+        for (candidates, ignore_missing) in includes {
+            let found = candidates
+                .iter()
+                .any(|candidate| tera.get_template(candidate).is_ok());
+            if !found && !ignore_missing {
+                return Err(anyhow!(
+                    "template `{name}` includes `{}`, which doesn't exist",
+                    candidates.join("`, `")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn collect_includes<'a>(nodes: &'a [Node], includes: &mut Vec<(&'a [String], bool)>) {
+    for node in nodes {
+        match node {
+            Node::Include(_, candidates, ignore_missing) => {
+                includes.push((candidates, *ignore_missing));
+            }
+            Node::Block(_, block, _) => collect_includes(&block.body, includes),
+            Node::Forloop(_, forloop, _) => {
+                collect_includes(&forloop.body, includes);
+                if let Some(body) = &forloop.empty_body {
+                    collect_includes(body, includes);
+                }
+            }
+            Node::If(branches, _) => {
+                for (_, _, body) in &branches.conditions {
+                    collect_includes(body, includes);
+                }
+                if let Some((_, body)) = &branches.otherwise {
+                    collect_includes(body, includes);
+                }
+            }
+            Node::FilterSection(_, section, _) => collect_includes(&section.body, includes),
+            Node::MacroDefinition(_, definition, _) => collect_includes(&definition.body, includes),
+            _ => {}
+        }
+    }
+}
+
+static PARSE_ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)Failed to parse "([^"]+)"[:\s]*--> (\d+):(\d+)\n.*?\n\s*= ([^\n]+)"#).unwrap()
+});
+
+/// Tera reports syntax errors as a pretty-printed block per file; boil each one
+/// down to `name:line:column: message`.
+fn load_error(error: &tera::Error, template_dirs: &[PathBuf]) -> String {
+    let message = error_chain(error);
+    let problems: Vec<String> = PARSE_ERROR
+        .captures_iter(&message)
+        .map(|caps| {
+            let path = Path::new(&caps[1]);
+            let name = template_dirs
+                .iter()
+                .find_map(|dir| path.strip_prefix(dir).ok())
+                .unwrap_or(path);
+            format!(
+                "invalid template {}:{}:{}: {}",
+                name.display(),
+                &caps[2],
+                &caps[3],
+                &caps[4]
+            )
+        })
+        .collect();
+
+    if problems.is_empty() {
+        message
+    } else {
+        problems.join("\n")
+    }
+}
+
+/// Tera keeps the useful part of an error (line, column, the actual problem) in
+/// its sources, so flatten the whole chain into one message.
+pub fn error_chain(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
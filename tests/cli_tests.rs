@@ -41,9 +41,14 @@ fn build_command_with_valid_path() -> Result<(), Box<dyn std::error::Error>> {
 fn build_command_with_invalid_path() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempdir()?;
     let invalid_path = temp_dir.path().join("non_existent_dir");
+    let config_path = temp_dir.path().join("arrow.conf");
+    std::fs::write(&config_path, "workspaces: {}\n")?;
 
     let mut cmd = Command::cargo_bin("arrow")?;
-    cmd.arg("build").arg("-e").arg(&invalid_path);
+    cmd.env("ARROW_CONFIG", &config_path)
+        .arg("build")
+        .arg("-e")
+        .arg(&invalid_path);
 
     cmd.assert()
         .failure()
@@ -139,3 +144,1705 @@ fn status_command_displays_status() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn publish_command_updates_front_matter_in_place() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let posts_dir = temp_dir.path().join("src").join("posts");
+    std::fs::create_dir_all(&posts_dir)?;
+
+    std::fs::write(
+        posts_dir.join("draft.djot"),
+        "---\ntitle: Draft\nstatus:   false # wip\ndesc: kept as is\n---\n# Draft\n\n---\n",
+    )?;
+    std::fs::write(posts_dir.join("other.djot"), "---\ntitle: Other\n---\nbody")?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("publish")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg("posts/*.djot");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("published: posts/draft.djot"))
+        .stdout(predicate::str::contains("published: posts/other.djot"));
+
+    assert_eq!(
+        std::fs::read_to_string(posts_dir.join("draft.djot"))?,
+        "---\ntitle: Draft\nstatus: true\ndesc: kept as is\n---\n# Draft\n\n---\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(posts_dir.join("other.djot"))?,
+        "---\ntitle: Other\nstatus: true\n---\nbody"
+    );
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("unpublish")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg(posts_dir.join("other.djot"));
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("unpublished: posts/other.djot"));
+
+    assert_eq!(
+        std::fs::read_to_string(posts_dir.join("other.djot"))?,
+        "---\ntitle: Other\nstatus: false\n---\nbody"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn publish_command_dry_run_shows_diff() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    std::fs::create_dir_all(&src_dir)?;
+
+    let original = "---\ntitle: Post\nstatus: false\ndate: 2020-01-01\n---\nbody";
+    std::fs::write(src_dir.join("post.djot"), original)?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("publish")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg("--date")
+        .arg("--dry-run")
+        .arg("post.djot");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("-status: false"))
+        .stdout(predicate::str::contains("+status: true"))
+        .stdout(predicate::str::contains("-date: 2020-01-01"));
+
+    assert_eq!(
+        std::fs::read_to_string(src_dir.join("post.djot"))?,
+        original
+    );
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("publish")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg("missing.djot");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no djot source files match"));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_exposes_table_of_contents() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(temp_dir.path().join("site.conf"), "anchors: true\n")?;
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\n---\n# Intro\n\n## Setup *steps*\n\n### Details\n\n# Usage\n",
+    )?;
+    std::fs::write(
+        src_dir.join("plain.djot"),
+        "---\nstatus: true\ntoc: false\n---\n# Intro\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "<nav>{% for h in page.toc %}[{{ h.id }}:{{ h.text }}\
+         {% for c in h.children %}({{ c.level }}:{{ c.text }}\
+         {% for g in c.children %}<{{ g.text }}>{% endfor %}){% endfor %}]\
+         {% endfor %}</nav>{{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert!(html.contains("<nav>[Intro:Intro(2:Setup steps<Details>)][Usage:Usage]</nav>"));
+    assert!(html.contains("<a class=\"anchor\" href=\"#Setup-steps\" aria-hidden=\"true\">#</a>"));
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("plain.html"))?;
+    assert!(html.contains("<nav></nav>"));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_renders_math() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        src_dir.join("math.djot"),
+        "---\nstatus: true\n---\nInline $`x^2` and display $$`\\frac{a}{b}`\n",
+    )?;
+    std::fs::write(
+        src_dir.join("plain.djot"),
+        "---\nstatus: true\n---\nNo math here\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "<html><head></head><body>{{ page.content | safe }}</body></html>",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("math.html"))?;
+    assert!(html.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+    assert!(html.contains("display=\"block\""));
+    assert!(!html.contains("\\("));
+
+    std::fs::write(temp_dir.path().join("site.conf"), "math: katex\n")?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("math.html"))?;
+    assert!(html.contains("<span class=\"math inline\">\\(x^2\\)</span>"));
+    assert!(html.contains("katex.min.js"));
+    assert!(html.contains("</script>\n</head>"));
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("plain.html"))?;
+    assert!(!html.contains("katex"));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_expands_shortcodes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let shortcodes_dir = temp_dir.path().join("templates").join("shortcodes");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&shortcodes_dir)?;
+
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\n---\n{src=\"/assets/cat.png\"}\n::: figure\nA *cat*.\n:::\n\n\
+         Watch [this]{.video id=abc}.\n\n::: note\nnot a shortcode\n:::\n",
+    )?;
+    std::fs::write(
+        src_dir.join("broken.djot"),
+        "---\nstatus: true\n---\n\nintro\n\n::: broken\nbody\n:::\n",
+    )?;
+    std::fs::write(
+        temp_dir.path().join("templates").join("layout.html"),
+        "{{ page.content | safe }}",
+    )?;
+    std::fs::write(
+        shortcodes_dir.join("figure.html"),
+        "<figure><img src=\"{{ src }}\"><figcaption>{{ body | safe }}</figcaption></figure>",
+    )?;
+    std::fs::write(
+        shortcodes_dir.join("video.html"),
+        "<video data-id=\"{{ id }}\" title=\"{{ body | striptags }}\"></video>",
+    )?;
+    std::fs::write(shortcodes_dir.join("broken.html"), "{{ missing }}")?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success().stderr(predicate::str::contains(
+        "broken.djot:7: shortcode `broken` failed",
+    ));
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert!(html.contains(
+        "<figure><img src=\"&#x2F;assets&#x2F;cat.png\"><figcaption><p>A <strong>cat</strong>.</p>\n\
+         </figcaption></figure>"
+    ));
+    assert!(html.contains("Watch <video data-id=\"abc\" title=\"this\"></video>."));
+    assert!(html.contains("<div class=\"note\">"));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_resolves_internal_links() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let notes_dir = src_dir.join("notes");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&notes_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\n---\nSee [[Other Note]], [[notes/draft|the draft]], \
+         [a link](notes/other.djot#part) and `[[code]]`.\n\nAlso [[Nowhere]].\n",
+    )?;
+    std::fs::write(
+        notes_dir.join("other.djot"),
+        "---\ntitle: Other note\nstatus: true\n---\nBack to [[index]] or [home](../index.djot).\n",
+    )?;
+    std::fs::write(
+        notes_dir.join("draft.djot"),
+        "---\nstatus: false\n---\nWIP\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning: index.djot:4: `[[notes/draft]]` links to an unpublished page",
+        ))
+        .stderr(predicate::str::contains(
+            "warning: index.djot:6: no page matches `[[Nowhere]]`",
+        ));
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert!(html.contains("<a href=\"/notes/other.html\" class=\"wikilink\">Other Note</a>"));
+    assert!(html.contains("<a href=\"/notes/draft.html\" class=\"wikilink\">the draft</a>"));
+    assert!(html.contains("<a href=\"/notes/other.html#part\">a link</a>"));
+    assert!(html.contains("<code>[[code]]</code>"));
+    assert!(html.contains("Also [[Nowhere]]."));
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist/notes/other.html"))?;
+    assert!(html.contains("<a href=\"/index.html\" class=\"wikilink\">index</a>"));
+    assert!(html.contains("<a href=\"/index.html\">home</a>"));
+
+    std::fs::write(temp_dir.path().join("site.conf"), "broken_links: error\n")?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("error processing file"))
+        .stderr(predicate::str::contains(
+            "build failed: 1 page(s) have broken links",
+        ));
+    assert!(!temp_dir.path().join("dist").join("index.html").exists());
+
+    Ok(())
+}
+
+#[test]
+fn build_command_exposes_backlinks_and_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(temp_dir.path().join("site.conf"), "graph: true\n")?;
+    std::fs::write(
+        src_dir.join("a.djot"),
+        "---\ntitle: Alpha\nstatus: true\n---\n[[c]] and [again](c.djot)\n",
+    )?;
+    std::fs::write(
+        src_dir.join("b.djot"),
+        "---\ntitle: Beta\nstatus: true\n---\n[[Gamma]] and [[Alpha]]\n",
+    )?;
+    std::fs::write(
+        src_dir.join("c.djot"),
+        "---\ntitle: Gamma\nstatus: true\n---\nno links\n",
+    )?;
+    std::fs::write(
+        src_dir.join("draft.djot"),
+        "---\nstatus: false\n---\n[[Gamma]]\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% for b in page.backlinks %}<{{ b.title }}|{{ b.url | safe }}>{% endfor %}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_dir = temp_dir.path().join("dist");
+    assert_eq!(
+        std::fs::read_to_string(dist_dir.join("c.html"))?,
+        "<Alpha|/a.html><Beta|/b.html>"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dist_dir.join("a.html"))?,
+        "<Beta|/b.html>"
+    );
+
+    let graph: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dist_dir.join("graph.json"))?)?;
+    assert_eq!(graph["nodes"].as_array().map(Vec::len), Some(3));
+    assert_eq!(
+        graph["edges"],
+        serde_json::json!([
+            { "source": "/a.html", "target": "/c.html" },
+            { "source": "/b.html", "target": "/c.html" },
+            { "source": "/b.html", "target": "/a.html" },
+        ])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn check_command_reports_broken_links() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(src_dir.join("assets"))?;
+    std::fs::create_dir_all(src_dir.join("posts"))?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(src_dir.join("assets").join("cat.png"), "png")?;
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\n---\n# Top\n\n![cat](/assets/cat.png) [post](/posts/one) \
+         [dir](/posts/) [top](#Top) [ext](https://example.invalid/) [mail](mailto:a@b.c)\n",
+    )?;
+    std::fs::write(
+        src_dir.join("posts").join("one.djot"),
+        "---\nstatus: true\n---\n[back](../index.html#Top)\n\n\
+         ![dog](/assets/dog.png)\n\n[gone](#missing)\n",
+    )?;
+    std::fs::write(
+        src_dir.join("posts").join("index.djot"),
+        "---\nstatus: true\n---\nposts\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("check").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("missing `dist` directory"));
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg("--check");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("build completed!"))
+        .stderr(predicate::str::contains(
+            "posts/one.html:3: `/assets/dog.png`: target does not exist",
+        ))
+        .stderr(predicate::str::contains(
+            "posts/one.html:4: `#missing`: no element with id `missing`",
+        ))
+        .stderr(predicate::str::contains("index.html:").not())
+        .stderr(predicate::str::contains("found 2 broken link(s)"));
+
+    std::fs::write(src_dir.join("assets").join("dog.png"), "png")?;
+    std::fs::write(
+        src_dir.join("posts").join("one.djot"),
+        "---\nstatus: true\n---\n[back](../index.html#Top)\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("check").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("no broken links found."));
+
+    Ok(())
+}
+
+#[test]
+fn lint_command_validates_front_matter() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "fields:\n  author:\n    type: string\n    required: true\n  tags:\n    type: list\n",
+    )?;
+    std::fs::write(
+        src_dir.join("bad.djot"),
+        "---\ntitel: Typo\ndate: 2024-13-01\nstatus: yes please\ntags: rust\n---\nbody",
+    )?;
+    std::fs::write(
+        src_dir.join("good.djot"),
+        "---\ntitle: Good\nauthor: me\ntags: [a, b]\nstatus: true\n---\nbody",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ page.extra.author }}:{{ page.extra.tags | join(sep=\",\") }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("lint").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "bad.djot:2: unknown field `titel`",
+        ))
+        .stderr(predicate::str::contains(
+            "bad.djot:3: field `date` has invalid date `2024-13-01`, expected YYYY-MM-DD",
+        ))
+        .stderr(predicate::str::contains(
+            "bad.djot:4: field `status` must be of type bool, found string `yes please`",
+        ))
+        .stderr(predicate::str::contains(
+            "bad.djot:5: field `tags` must be of type list, found string `rust`",
+        ))
+        .stderr(predicate::str::contains(
+            "bad.djot:2: missing required field `author`",
+        ))
+        .stderr(predicate::str::contains("good.djot").not())
+        .stderr(predicate::str::contains("found 5 front matter problem(s)"));
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success().stderr(predicate::str::contains(
+        "warning: bad.djot:2: unknown field `titel`",
+    ));
+
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("dist").join("good.html"))?,
+        "me:a,b"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn build_command_processes_images() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let assets_dir = src_dir.join("assets").join("img");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&assets_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    let pixels = image::RgbImage::from_pixel(100, 50, image::Rgb([200, 10, 10]));
+    pixels.save(assets_dir.join("photo.png"))?;
+    pixels.save(assets_dir.join("shot.webp"))?;
+    // A JPEG with an EXIF segment right after the start of image marker.
+    let mut jpeg = Vec::new();
+    pixels.write_to(
+        &mut std::io::Cursor::new(&mut jpeg),
+        image::ImageFormat::Jpeg,
+    )?;
+    let exif = b"\xFF\xE1\x00\x0EExif\x00\x00camera";
+    let with_exif = [&jpeg[..2], exif, &jpeg[2..]].concat();
+    std::fs::write(assets_dir.join("snap.jpeg"), &with_exif)?;
+
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "images:\n  widths: [40, 200]\n  formats: [avif, webp]\n  sizes: 50vw\n",
+    )?;
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\n---\n![A red *box*](/assets/img/photo.png){.wide}\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% set img = image(path=\"img/photo.png\") %}{{ img.width }}x{{ img.height }}\n\
+         {{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_img = temp_dir.path().join("dist").join("assets").join("img");
+    for name in [
+        "photo.png",
+        "photo-40w.png",
+        "photo.avif",
+        "photo-40w.avif",
+        "photo.webp",
+        "photo-40w.webp",
+        "shot-40w.webp",
+        "shot-40w.avif",
+        "snap-40w.jpg",
+    ] {
+        assert!(dist_img.join(name).exists(), "missing {name}");
+    }
+    assert!(!dist_img.join("photo-200w.png").exists());
+    // Metadata is cut out of the JPEG without touching the image data, and the
+    // WebP original is published as it is.
+    assert_eq!(std::fs::read(dist_img.join("snap.jpeg"))?, jpeg);
+    assert!(!dist_img.join("snap.jpg").exists());
+    assert_eq!(
+        std::fs::read(dist_img.join("shot.webp"))?,
+        std::fs::read(assets_dir.join("shot.webp"))?
+    );
+    assert_eq!(
+        image::image_dimensions(dist_img.join("photo-40w.png"))?,
+        (40, 20)
+    );
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert!(html.starts_with("100x50\n"));
+    assert!(html.contains(
+        "<picture><source type=\"image/avif\" \
+         srcset=\"/assets/img/photo-40w.avif 40w, /assets/img/photo.avif 100w\" sizes=\"50vw\">\
+         <source type=\"image/webp\" \
+         srcset=\"/assets/img/photo-40w.webp 40w, /assets/img/photo.webp 100w\" sizes=\"50vw\">"
+    ));
+    assert!(html.contains(
+        "<img alt=\"A red box\" src=\"/assets/img/photo.png\" \
+         srcset=\"/assets/img/photo-40w.png 40w, /assets/img/photo.png 100w\" sizes=\"50vw\" \
+         width=\"100\" height=\"50\" class=\"wide\"></picture>"
+    ));
+
+    let cache_dir = temp_dir.path().join(".arrow-cache").join("images");
+    let cached = std::fs::read_dir(&cache_dir)?.count();
+    // photo: 6 variants, snap: 5 without its full-size original, shot: 3
+    // without its full-size original or a second WebP.
+    assert_eq!(cached, 14);
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+    assert_eq!(std::fs::read_dir(&cache_dir)?.count(), cached);
+    assert!(dist_img.join("photo-40w.avif").exists());
+
+    // Cached variants of removed images are deleted.
+    for name in ["photo.png", "shot.webp", "snap.jpeg"] {
+        std::fs::remove_file(assets_dir.join(name))?;
+    }
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+    assert_eq!(std::fs::read_dir(&cache_dir)?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn build_command_minifies_and_fingerprints_assets() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let css_dir = src_dir.join("assets").join("css");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&css_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "minify: true\nfingerprint: true\n",
+    )?;
+    std::fs::write(css_dir.join("global.css"), "body {\n  color: red;\n}\n")?;
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\n---\nSome   text.\n\n```\nkeep   this\n```\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "<html>\n  <!-- layout -->\n  <link rel=\"stylesheet\" \
+         href=\"{{ asset_url(path=\"css/global.css\") | safe }}\">\n  \
+         <body>\n    {{ page.content | safe }}\n  </body>\n</html>\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_dir = temp_dir.path().join("dist");
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+        dist_dir.join("assets-manifest.json"),
+    )?)?;
+    let hashed = manifest["css/global.css"].as_str().unwrap();
+    assert!(hashed.starts_with("css/global.") && hashed.ends_with(".css"));
+    assert_eq!(hashed.len(), "css/global.123456.css".len());
+
+    let css = std::fs::read_to_string(dist_dir.join("assets").join(hashed))?;
+    assert_eq!(css, "body{color:red;}");
+    assert!(!dist_dir.join("assets/css/global.css").exists());
+
+    let html = std::fs::read_to_string(dist_dir.join("index.html"))?;
+    assert!(html.contains(&format!("href=\"/assets/{hashed}\"")));
+    assert!(!html.contains("<!-- layout -->"));
+    assert!(html.contains("<body> <p>Some text.</p>"));
+    assert!(html.contains("keep   this\n"));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_compiles_scss() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let css_dir = src_dir.join("assets").join("css");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&css_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(css_dir.join("_colors.scss"), "$accent: #c00;\n")?;
+    std::fs::write(
+        css_dir.join("global.scss"),
+        "@use \"colors\";\n\nnav {\n  a { color: colors.$accent; }\n}\n",
+    )?;
+    std::fs::write(css_dir.join("broken.scss"), "body {\n  color: red;\n}}\n")?;
+    std::fs::write(src_dir.join("index.djot"), "---\nstatus: true\n---\nhi\n")?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("error copying assets from"))
+        .stderr(predicate::str::contains("broken.scss:3: unmatched \"}\"."));
+    assert!(!temp_dir.path().join("dist").join("index.html").exists());
+
+    std::fs::remove_file(css_dir.join("broken.scss"))?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_css = temp_dir.path().join("dist").join("assets").join("css");
+    assert_eq!(
+        std::fs::read_to_string(dist_css.join("global.css"))?,
+        "nav a {\n  color: #c00;\n}\n"
+    );
+    assert!(!dist_css.join("_colors.css").exists());
+    assert!(!dist_css.join("global.scss").exists());
+    assert!(!dist_css.join("broken.css").exists());
+
+    Ok(())
+}
+
+#[test]
+fn build_command_respects_arrowignore() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let assets_dir = src_dir.join("assets");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(src_dir.join("drafts"))?;
+    std::fs::create_dir_all(assets_dir.join("raw"))?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        temp_dir.path().join(".arrowignore"),
+        "src/drafts/\n*.psd\nsrc/assets/originals/\n",
+    )?;
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "copy: hardlink\nfollow_symlinks: true\nimages:\n  widths: [8]\n",
+    )?;
+    std::fs::write(src_dir.join("index.djot"), "---\nstatus: true\n---\nhi\n")?;
+    std::fs::write(
+        src_dir.join("drafts").join("idea.djot"),
+        "---\nstatus: true\n---\nsecret\n",
+    )?;
+    std::fs::write(assets_dir.join("logo.png"), "png")?;
+    std::fs::write(assets_dir.join("logo.psd"), "psd")?;
+    std::fs::write(assets_dir.join(".DS_Store"), "")?;
+    std::fs::write(assets_dir.join("raw").join("clip.txt"), "clip")?;
+    std::fs::create_dir_all(assets_dir.join("originals"))?;
+    image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 200]))
+        .save(assets_dir.join("originals").join("big.png"))?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ page.content | safe }}",
+    )?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(assets_dir.join("raw"), assets_dir.join("media"))?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_dir = temp_dir.path().join("dist");
+    let dist_assets = dist_dir.join("assets");
+    assert!(dist_dir.join("index.html").exists());
+    assert!(!dist_dir.join("drafts").exists());
+    assert!(dist_assets.join("logo.png").exists());
+    assert!(!dist_assets.join("logo.psd").exists());
+    assert!(!dist_assets.join(".DS_Store").exists());
+    assert!(!dist_assets.join("originals").exists());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(
+            std::fs::metadata(dist_assets.join("logo.png"))?.ino(),
+            std::fs::metadata(assets_dir.join("logo.png"))?.ino()
+        );
+        assert!(dist_assets.join("media").join("clip.txt").is_file());
+
+        std::os::unix::fs::symlink(assets_dir.join("missing"), assets_dir.join("dangling"))?;
+        let mut cmd = Command::cargo_bin("arrow")?;
+        cmd.arg("build").arg("-e").arg(temp_dir.path());
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "failed to read source directory: IO error for operation on",
+        ));
+
+        // An unreadable asset outside the source directory fails the build too.
+        let styles_dir = temp_dir.path().join("styles");
+        std::fs::create_dir_all(&styles_dir)?;
+        std::fs::rename(assets_dir.join("dangling"), styles_dir.join("dangling"))?;
+        std::fs::write(
+            temp_dir.path().join("site.conf"),
+            "follow_symlinks: true\ndirs:\n  assets: styles\n",
+        )?;
+        let mut cmd = Command::cargo_bin("arrow")?;
+        cmd.arg("build").arg("-e").arg(temp_dir.path());
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("error copying assets from"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn build_command_copies_static_and_page_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let trip_dir = src_dir.join("posts").join("trip");
+    let static_dir = temp_dir.path().join("static");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&trip_dir)?;
+    std::fs::create_dir_all(static_dir.join(".well-known"))?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(static_dir.join("CNAME"), "example.com\n")?;
+    std::fs::write(static_dir.join(".well-known").join("security.txt"), "hi")?;
+    std::fs::write(
+        trip_dir.join("index.djot"),
+        "---\nstatus: true\n---\n![beach](photo.jpg)\n",
+    )?;
+    std::fs::write(trip_dir.join("photo.jpg"), "jpg")?;
+    let draft_dir = src_dir.join("posts").join("draft");
+    std::fs::create_dir_all(draft_dir.join("img"))?;
+    std::fs::write(
+        draft_dir.join("index.djot"),
+        "---\nstatus: false\n---\n![secret](img/plan.jpg)\n",
+    )?;
+    std::fs::write(draft_dir.join("img").join("plan.jpg"), "jpg")?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_dir = temp_dir.path().join("dist");
+    assert_eq!(
+        std::fs::read_to_string(dist_dir.join("CNAME"))?,
+        "example.com\n"
+    );
+    assert!(dist_dir.join(".well-known").join("security.txt").exists());
+    let dist_trip = dist_dir.join("posts").join("trip");
+    assert!(dist_trip.join("index.html").exists());
+    assert_eq!(std::fs::read_to_string(dist_trip.join("photo.jpg"))?, "jpg");
+    assert!(!dist_trip.join("index.djot").exists());
+    assert!(!dist_dir.join("posts").join("draft").exists());
+
+    std::fs::write(temp_dir.path().join("site.conf"), "drafts: true\n")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+    assert!(dist_dir.join("posts/draft/img/plan.jpg").exists());
+
+    Ok(())
+}
+
+#[test]
+fn build_command_exposes_data_files() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let data_dir = temp_dir.path().join("data");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(data_dir.join("social"))?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        data_dir.join("nav.yaml"),
+        "- title: Home\n  url: /\n- title: Blog\n  url: /blog\n",
+    )?;
+    std::fs::write(data_dir.join("site.json"), r#"{"owner": "Ada"}"#)?;
+    std::fs::write(data_dir.join("build.toml"), "year = 2024\n")?;
+    std::fs::write(
+        data_dir.join("projects.csv"),
+        "name,lang\narrow,rust\nquiver,go\n",
+    )?;
+    std::fs::write(
+        data_dir.join("social").join("links.yaml"),
+        "mastodon: \"@ada\"\n",
+    )?;
+    std::fs::write(src_dir.join("index.djot"), "---\nstatus: true\n---\nhi\n")?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% for item in data.nav %}{{ item.title }};{% endfor %}\
+         {{ data.site.owner }} {{ data.build.year }} \
+         {% for p in data.projects %}{{ p.name }}={{ p.lang }};{% endfor %}\
+         {{ data.social.links.mastodon }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert_eq!(html, "Home;Blog;Ada 2024 arrow=rust;quiver=go;@ada");
+
+    std::fs::write(data_dir.join("broken.json"), "{")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().failure().stderr(
+        predicate::str::contains("invalid data file").and(predicate::str::contains("broken.json")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn build_command_exposes_site_build_and_menus() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "site:\n  title: My Site\n  base_url: https://example.com\n  author: Ada\n\
+         menus:\n  main:\n    - name: Source\n      url: https://git.example.com\n      weight: 100\n",
+    )?;
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\ntitle: Home\nstatus: true\nmenu:\n  main:\n    weight: 1\n---\nhi\n",
+    )?;
+    std::fs::write(
+        src_dir.join("about.djot"),
+        "---\ntitle: About\nstatus: true\nmenu: [main, footer]\n---\nabout\n",
+    )?;
+    std::fs::write(
+        src_dir.join("draft.djot"),
+        "---\ntitle: Draft\nmenu: main\n---\nwip\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ site.title }}|{{ site.base_url | safe }}|{{ site.author }}|{{ build.env }}|\
+         {{ build.version }}|\
+         {% for item in menus.main %}{% if item.url == page.url %}*{% endif %}\
+         {{ item.name }}={{ item.url | safe }};{% endfor %}|\
+         {% for item in menus.footer %}{{ item.name }}{% endfor %}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("about.html"))?;
+    assert_eq!(
+        html,
+        format!(
+            "My Site|https://example.com|Ada|prod|{}|\
+             *About=/about.html;Home=/index.html;Source=https://git.example.com;|About",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn templates_can_use_arrow_functions() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let posts_dir = src_dir.join("posts");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&posts_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        src_dir.join("index.djot"),
+        "---\nstatus: true\ndate: 2024-03-05\ntagline: \"*Hello* there\"\n---\nhome\n",
+    )?;
+    std::fs::write(
+        posts_dir.join("old.djot"),
+        "---\ntitle: Old\nstatus: true\ndate: 2023-01-01\n---\nold\n",
+    )?;
+    std::fs::write(
+        posts_dir.join("new.djot"),
+        "---\ntitle: New\nstatus: true\ndate: 2024-01-01\n---\nnew\n",
+    )?;
+    std::fs::write(posts_dir.join("wip.djot"), "---\ntitle: WIP\n---\nwip\n")?;
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "fields:\n  tagline:\n    type: string\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% if page.url == \"/index.html\" %}\
+         {% set old = get_page(path=\"posts/old.djot\") %}{{ old.title }}|\
+         {{ url_for(path=\"posts/new.djot\") | safe }}|\
+         {% set posts = get_section(path=\"posts\") %}\
+         {% for p in posts.pages %}{{ p.title }};{% endfor %}|\
+         {{ asset_url(path=\"css/site.css\") | safe }}|\
+         {{ page.extra.tagline | djotify | safe }}|\
+         {{ \"one two three\" | reading_time }}|\
+         {{ page.date | date_format(format=\"%A %e %B %Y\", locale=\"fr_FR\") }}\
+         {% endif %}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert_eq!(
+        html,
+        "Old|/posts/new.html|New;Old;|/assets/css/site.css|\
+         <p><strong>Hello</strong> there</p>\n|1|mardi  5 mars 2024"
+    );
+
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% set wip = get_page(path=\"posts/wip.djot\") %}{{ wip.title }}",
+    )?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success().stderr(predicate::str::contains(
+        "get_page() found no published page `posts/wip.djot`",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_computes_word_count_and_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let posts_dir = src_dir.join("posts");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&posts_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+
+    std::fs::write(
+        posts_dir.join("marker.djot"),
+        "---\nstatus: true\n---\n# Trip\n\nWe went *north* today.\n\n{% more %}\n\n\
+         The rest.\n\n```\nnot counted at all\n```\n",
+    )?;
+    std::fs::write(
+        posts_dir.join("paragraph.djot"),
+        "---\nstatus: true\n---\nFirst one.\n\nSecond one.\n",
+    )?;
+    std::fs::write(
+        posts_dir.join("list.djot"),
+        "---\nstatus: true\ndesc: Just a list\n---\n- a\n- b\n",
+    )?;
+    std::fs::write(
+        posts_dir.join("links.djot"),
+        "---\nstatus: true\n---\nSee [[paragraph]] and [the list](list.djot).\n",
+    )?;
+    std::fs::write(src_dir.join("index.djot"), "---\nstatus: true\n---\nhome\n")?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% if page.url == \"/index.html\" %}\
+         {% set posts = get_section(path=\"posts\") %}\
+         {% for p in posts.pages %}{{ p.title }}:{{ p.word_count }}:{{ p.summary | safe }}|{% endfor %}\
+         {% else %}{{ page.word_count }} {{ page.reading_time }}|{{ page.summary | safe }}{% endif %}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist_posts = temp_dir.path().join("dist").join("posts");
+    assert_eq!(
+        std::fs::read_to_string(dist_posts.join("marker.html"))?,
+        "7 1|<section id=\"Trip\">\n<h1>Trip</h1>\n<p>We went <strong>north</strong> today.</p>\n</section>"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dist_posts.join("paragraph.html"))?,
+        "4 1|<p>First one.</p>"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dist_posts.join("list.html"))?,
+        "2 1|<p>Just a list</p>"
+    );
+    let index = std::fs::read_to_string(temp_dir.path().join("dist").join("index.html"))?;
+    assert!(index.contains("paragraph:4:<p>First one.</p>|"));
+    assert!(index.contains("list:2:<p>Just a list</p>|"));
+    // Listing summaries go through the same link resolution as the page.
+    assert!(index.contains(
+        "links:5:<p>See <a href=\"/posts/paragraph.html\" class=\"wikilink\">paragraph</a> \
+         and <a href=\"/posts/list.html\">the list</a>.</p>|"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn templates_are_validated_before_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(templates_dir.join("partials"))?;
+
+    std::fs::write(src_dir.join("a.djot"), "---\nstatus: true\n---\na\n")?;
+    std::fs::write(src_dir.join("b.djot"), "---\nstatus: true\n---\nb\n")?;
+    std::fs::write(
+        templates_dir.join("partials").join("nav.html"),
+        "<nav></nav>",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% include \"partials/nav.html\" %}\n{{ page.content | safe }}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("templates")
+        .arg("check")
+        .arg("-e")
+        .arg(temp_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("2 template(s) are valid."));
+
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{% include \"partials/footer.html\" %}",
+    )?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("templates")
+        .arg("check")
+        .arg("-e")
+        .arg(temp_dir.path());
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "template `layout.html` includes `partials/footer.html`, which doesn't exist",
+    ));
+
+    std::fs::write(templates_dir.join("layout.html"), "<p>\n{{ page.title }\n")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid template layout.html:2:15: expected",
+        ))
+        .stderr(predicate::str::contains("error processing").not());
+
+    std::fs::write(templates_dir.join("layout.html"), "{{ page.missing }}")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success().stderr(predicate::str::contains(
+        "error processing 2 files: Failed to render 'layout.html': Variable `page.missing` not found",
+    ));
+
+    std::fs::remove_file(templates_dir.join("layout.html"))?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("using the built-in layout"));
+    let html = std::fs::read_to_string(temp_dir.path().join("dist").join("a.html"))?;
+    assert!(html.contains("<title>a</title>"));
+    assert!(html.contains("<p>a</p>"));
+
+    Ok(())
+}
+
+#[test]
+fn init_command_scaffolds_and_registers_workspace() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_home = temp_dir.path().join("config");
+    let workspace = temp_dir.path().join("my-site");
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("XDG_CONFIG_HOME", &config_home)
+        .arg("init")
+        .arg(&workspace)
+        .arg("--name")
+        .arg("blog");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("created workspace `blog`"));
+
+    assert!(workspace.join("templates").join("layout.html").exists());
+    assert!(
+        workspace
+            .join("src")
+            .join("assets")
+            .join("css")
+            .join("global.css")
+            .exists()
+    );
+    assert!(workspace.join("src").join("index.djot").exists());
+
+    let config = std::fs::read_to_string(config_home.join("arrow").join("arrow.conf"))?;
+    let config: serde_yaml::Value = serde_yaml::from_str(&config)?;
+    assert_eq!(
+        config["workspaces"]["blog"]["path"].as_str(),
+        Some(workspace.canonicalize()?.to_str().unwrap())
+    );
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("XDG_CONFIG_HOME", &config_home)
+        .arg("build")
+        .arg("-e")
+        .arg("blog");
+    cmd.assert().success();
+    let html = std::fs::read_to_string(workspace.join("dist").join("index.html"))?;
+    assert!(html.contains("<link rel=\"stylesheet\" href=\"/assets/css/global.css\" />"));
+    assert!(html.contains("<h1>Hello</h1>"));
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("XDG_CONFIG_HOME", &config_home)
+        .arg("init")
+        .arg(temp_dir.path().join("other"))
+        .arg("--name")
+        .arg("blog");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("workspace `blog` already exists"));
+    assert!(!temp_dir.path().join("other").join("src").exists());
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("XDG_CONFIG_HOME", &config_home)
+        .arg("init")
+        .arg(&workspace);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("already contains a workspace"));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_uses_theme_with_workspace_overrides() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let theme_dir = temp_dir.path().join("themes").join("plain");
+    let theme_templates = theme_dir.join("templates");
+    let theme_css = theme_dir.join("assets").join("css");
+
+    std::fs::create_dir_all(src_dir.join("assets").join("css"))?;
+    std::fs::create_dir_all(theme_templates.join("partials"))?;
+    std::fs::create_dir_all(&theme_css)?;
+    std::fs::create_dir_all(temp_dir.path().join("templates").join("partials"))?;
+
+    std::fs::write(src_dir.join("index.djot"), "---\nstatus: true\n---\nhome\n")?;
+    std::fs::write(
+        theme_templates.join("layout.html"),
+        "{% include \"partials/footer.html\" %}|{% include \"partials/nav.html\" %}",
+    )?;
+    std::fs::write(
+        theme_templates.join("partials").join("footer.html"),
+        "theme footer",
+    )?;
+    std::fs::write(
+        theme_templates.join("partials").join("nav.html"),
+        "theme nav",
+    )?;
+    std::fs::write(
+        temp_dir
+            .path()
+            .join("templates")
+            .join("partials")
+            .join("nav.html"),
+        "my nav",
+    )?;
+    std::fs::write(theme_css.join("base.css"), "p{}")?;
+    std::fs::write(theme_css.join("site.css"), "theme")?;
+    std::fs::write(src_dir.join("assets").join("css").join("site.css"), "mine")?;
+
+    std::fs::write(temp_dir.path().join("site.conf"), "theme: plain\n")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+
+    let dist = temp_dir.path().join("dist");
+    let html = std::fs::read_to_string(dist.join("index.html"))?;
+    assert_eq!(html, "theme footer|my nav");
+    let css = dist.join("assets").join("css");
+    assert_eq!(std::fs::read_to_string(css.join("base.css"))?, "p{}");
+    assert_eq!(std::fs::read_to_string(css.join("site.css"))?, "mine");
+    assert_eq!(
+        std::fs::read_to_string(theme_css.join("site.css"))?,
+        "theme"
+    );
+
+    // A stylesheet compiled over a hard-linked theme file leaves the theme alone.
+    std::fs::write(
+        src_dir.join("assets").join("css").join("base.scss"),
+        "a { b: c; }",
+    )?;
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "theme: plain\ncopy: hardlink\n",
+    )?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+    assert!(std::fs::read_to_string(css.join("base.css"))?.contains("b: c"));
+    assert_eq!(std::fs::read_to_string(theme_css.join("base.css"))?, "p{}");
+
+    std::fs::write(temp_dir.path().join("site.conf"), "theme: missing\n")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("theme `missing` not found at"));
+
+    Ok(())
+}
+
+#[test]
+fn config_and_workspace_commands_manage_config() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_path = temp_dir.path().join("arrow.conf");
+    let site_dir = temp_dir.path().join("site");
+    let notes_dir = temp_dir.path().join("notes");
+    std::fs::create_dir_all(site_dir.join("src"))?;
+    std::fs::create_dir_all(notes_dir.join("src"))?;
+
+    let arrow = |args: &[&str]| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("arrow")?;
+        cmd.env("ARROW_CONFIG", &config_path).args(args);
+        Ok(cmd)
+    };
+
+    arrow(&["config", "path"])?
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(ARROW_CONFIG, not found)"));
+
+    for (name, dir) in [("site", &site_dir), ("notes", &notes_dir)] {
+        arrow(&["workspace", "add", name, dir.to_str().unwrap()])?
+            .assert()
+            .success();
+    }
+    arrow(&["workspace", "add", "site", notes_dir.to_str().unwrap()])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("workspace `site` already exists"));
+    arrow(&["workspace", "add", "gone", "/no/such/dir"])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a directory"));
+
+    // Settings arrow doesn't manage survive the rewrites below.
+    let mut config = std::fs::read_to_string(&config_path)?;
+    config.push_str("site:\n  title: Mine\n");
+    std::fs::write(&config_path, config)?;
+
+    arrow(&["workspace", "set-default", "notes"])?
+        .assert()
+        .success();
+    let output = arrow(&["config", "show"])?.output()?;
+    let shown = String::from_utf8(output.stdout)?;
+    let notes = shown.find("notes:").unwrap();
+    let site = shown.find("site:").unwrap();
+    assert!(notes < site, "notes should come first:\n{shown}");
+
+    arrow(&["workspace", "remove", "site"])?.assert().success();
+    arrow(&["workspace", "remove", "site"])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "workspace `site` not found in {config_path:?}"
+        )));
+    let config = std::fs::read_to_string(&config_path)?;
+    assert!(!config.contains("site:\n    path"));
+    assert!(config.contains("notes:"));
+    assert!(config.contains("title: Mine"));
+
+    // --config takes precedence over ARROW_CONFIG.
+    let other_path = temp_dir.path().join("other.conf");
+    arrow(&["--config", other_path.to_str().unwrap(), "config", "path"])?
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(other_path.to_str().unwrap()));
+
+    Ok(())
+}
+
+#[test]
+fn config_is_looked_up_in_xdg_config_home() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_dir = temp_dir.path().join("config");
+    let user_config = config_dir.join("arrow").join("arrow.conf");
+    let site_dir = temp_dir.path().join("site");
+    std::fs::create_dir_all(site_dir.join("src"))?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env_remove("ARROW_CONFIG")
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .arg("config")
+        .arg("path");
+    let assert = cmd
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{} (user, not found)",
+            user_config.display()
+        )));
+    if cfg!(unix) {
+        assert.stdout(predicate::str::contains("/etc/arrow/arrow.conf (system"));
+    }
+
+    std::fs::create_dir_all(user_config.parent().unwrap())?;
+    std::fs::write(
+        &user_config,
+        format!("workspaces:\n  site:\n    path: {:?}\n", site_dir),
+    )?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env_remove("ARROW_CONFIG")
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .arg("config")
+        .arg("path");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{} (user)",
+            user_config.display()
+        )));
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env_remove("ARROW_CONFIG")
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .arg("build")
+        .arg("-e")
+        .arg("site");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("build completed!"));
+
+    Ok(())
+}
+
+#[test]
+fn workspace_commands_leave_system_settings_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let config_dir = temp_dir.path().join("config");
+    let user_config = config_dir.join("arrow").join("arrow.conf");
+    let system_config = temp_dir.path().join("system.conf");
+    let site_dir = temp_dir.path().join("site");
+    let shared_dir = temp_dir.path().join("shared");
+    std::fs::create_dir_all(site_dir.join("src"))?;
+    std::fs::create_dir_all(shared_dir.join("src"))?;
+    std::fs::write(
+        &system_config,
+        format!("workspaces:\n  shared:\n    path: {shared_dir:?}\nserver:\n  port: 4321\n"),
+    )?;
+
+    let arrow = |args: &[&str]| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("arrow")?;
+        cmd.env_remove("ARROW_CONFIG")
+            .env("ARROW_SYSTEM_CONFIG", &system_config)
+            .env("XDG_CONFIG_HOME", &config_dir)
+            .args(args);
+        Ok(cmd)
+    };
+
+    arrow(&["workspace", "add", "site", site_dir.to_str().unwrap()])?
+        .assert()
+        .success();
+    let saved = std::fs::read_to_string(&user_config)?;
+    assert!(!saved.contains("server"), "server was saved:\n{saved}");
+    arrow(&["config", "show", "--resolved"])?
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "server.port: 4321  # {}",
+            system_config.display()
+        )));
+
+    arrow(&["workspace", "remove", "shared"])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "workspace `shared` is defined in the system config {system_config:?}"
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn build_command_uses_configured_dirs_and_protects_output() -> Result<(), Box<dyn std::error::Error>>
+{
+    let temp_dir = tempdir()?;
+    let workspace = temp_dir.path().join("site");
+    let content_dir = workspace.join("content");
+    let layouts_dir = workspace.join("layouts");
+    let styles_dir = workspace.join("styles");
+
+    std::fs::create_dir_all(&content_dir)?;
+    std::fs::create_dir_all(&layouts_dir)?;
+    std::fs::create_dir_all(&styles_dir)?;
+    std::fs::write(
+        content_dir.join("index.djot"),
+        "---\nstatus: true\n---\nhome\n",
+    )?;
+    std::fs::write(
+        layouts_dir.join("layout.html"),
+        "custom {{ page.content | safe }}",
+    )?;
+    std::fs::write(styles_dir.join("site.css"), "p{}")?;
+    std::fs::write(
+        workspace.join("site.conf"),
+        "dirs:\n  source: content\n  output: public\n  assets: styles\n  templates: layouts\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(&workspace);
+    cmd.assert().success();
+
+    let public = workspace.join("public");
+    let html = std::fs::read_to_string(public.join("index.html"))?;
+    assert_eq!(html, "custom \n<p>home</p>\n");
+    assert!(public.join("assets").join("site.css").exists());
+    assert!(!workspace.join("dist").exists());
+
+    // A non-empty directory outside the workspace is never cleared.
+    let deploy = temp_dir.path().join("deploy");
+    std::fs::create_dir_all(deploy.join(".git"))?;
+    std::fs::write(deploy.join("CNAME"), "example.com")?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build")
+        .arg("-e")
+        .arg(&workspace)
+        .arg("--out")
+        .arg(&deploy);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("it is outside the workspace"));
+    assert!(deploy.join("CNAME").exists());
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build")
+        .arg("-e")
+        .arg(&workspace)
+        .arg("--out")
+        .arg(&deploy)
+        .arg("--no-clean");
+    cmd.assert().success();
+    assert!(deploy.join("CNAME").exists());
+    assert!(deploy.join(".git").exists());
+    assert!(deploy.join("index.html").exists());
+
+    // Nor is a git checkout inside the workspace.
+    std::fs::create_dir_all(public.join(".git"))?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(&workspace);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("it contains a .git directory"));
+    assert!(public.join(".git").exists());
+
+    std::fs::write(
+        workspace.join("site.conf"),
+        "dirs:\n  source: content\n  output: .\n",
+    )?;
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(&workspace);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "it contains the workspace sources",
+    ));
+    assert!(content_dir.join("index.djot").exists());
+
+    Ok(())
+}
+
+#[test]
+fn build_command_applies_environment_settings() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempdir()?;
+    let src_dir = temp_dir.path().join("src");
+    let templates_dir = temp_dir.path().join("templates");
+    let dist_dir = temp_dir.path().join("dist");
+
+    std::fs::create_dir_all(&src_dir)?;
+    std::fs::create_dir_all(&templates_dir)?;
+    std::fs::write(src_dir.join("index.djot"), "---\nstatus: true\n---\nhome\n")?;
+    std::fs::write(
+        src_dir.join("draft.djot"),
+        "---\nstatus: false\n---\ndraft\n",
+    )?;
+    std::fs::write(
+        templates_dir.join("layout.html"),
+        "{{ build.env }}|{{ site.title }}|{{ site.base_url | safe }}|{{ page.draft }}",
+    )?;
+    std::fs::write(
+        temp_dir.path().join("site.conf"),
+        "site:\n  title: Notes\n  base_url: https://example.com\n\
+         environments:\n  staging:\n    site:\n      base_url: https://staging.example.com\n    drafts: true\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build").arg("-e").arg(temp_dir.path());
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(dist_dir.join("index.html"))?,
+        "prod|Notes|https://example.com|false"
+    );
+    assert!(!dist_dir.join("draft.html").exists());
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg("--env")
+        .arg("staging");
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(dist_dir.join("index.html"))?,
+        "staging|Notes|https://staging.example.com|false"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dist_dir.join("draft.html"))?,
+        "staging|Notes|https://staging.example.com|true"
+    );
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.arg("build")
+        .arg("-e")
+        .arg(temp_dir.path())
+        .arg("--env")
+        .arg("qa");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "environment `qa` is not defined in",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn settings_can_be_overridden_from_env_and_command_line() -> Result<(), Box<dyn std::error::Error>>
+{
+    let temp_dir = tempdir()?;
+    let workspace = temp_dir.path().join("site");
+    let config_path = temp_dir.path().join("arrow.conf");
+    std::fs::create_dir_all(workspace.join("src"))?;
+    std::fs::create_dir_all(workspace.join("templates"))?;
+    std::fs::write(
+        workspace.join("src").join("index.djot"),
+        "---\nstatus: true\n---\nhome\n",
+    )?;
+    std::fs::write(
+        workspace.join("templates").join("layout.html"),
+        "{{ site.title }}|{{ site.base_url | safe }}",
+    )?;
+    std::fs::write(
+        workspace.join("site.conf"),
+        "site:\n  title: Notes\n  base_url: https://example.com\n",
+    )?;
+    std::fs::write(
+        &config_path,
+        format!("workspaces:\n  site:\n    path: {workspace:?}\nserver:\n  port: 4321\n"),
+    )?;
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("ARROW_CONFIG", &config_path)
+        .env("ARROW_BASE_URL", "https://ci.example.com")
+        .args(["build", "-e", "site", "--set", "site.title=CI Notes"]);
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(workspace.join("dist").join("index.html"))?,
+        "CI Notes|https://ci.example.com"
+    );
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("ARROW_CONFIG", &config_path)
+        .env("ARROW_SERVER_PORT", "9000")
+        .env("ARROW_BASE_URL", "https://ci.example.com")
+        .args(["config", "show", "--resolved", "-e", "site"])
+        .args([
+            "--set",
+            "site.base_url=https://set.example.com",
+            "--set",
+            "minify=true",
+        ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "server.port: 9000  # ARROW_SERVER_PORT",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "workspaces.site.path: {}  # {}",
+            workspace.display(),
+            config_path.display()
+        )))
+        .stdout(predicate::str::contains(
+            "site.base_url: https://set.example.com  # --set site.base_url",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "site.title: Notes  # {}",
+            workspace.join("site.conf").display()
+        )))
+        .stdout(predicate::str::contains("minify: true  # --set minify"))
+        .stdout(predicate::str::contains("graph: false  # default"));
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("ARROW_CONFIG", &config_path)
+        .args(["config", "show", "--set", "site.title"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "invalid --set `site.title`, expected KEY=VALUE",
+    ));
+
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("ARROW_CONFIG", &config_path).args([
+        "config",
+        "show",
+        "--set",
+        "sit.base_url=https://x.example.com",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown setting `sit.base_url`"));
+
+    // Overrides only apply to the current run and never end up in the file.
+    let mut cmd = Command::cargo_bin("arrow")?;
+    cmd.env("ARROW_CONFIG", &config_path)
+        .env("ARROW_BASE_URL", "https://ci.example.com")
+        .args(["--set", "server.port=9000", "--set", "minify=true"])
+        .args(["workspace", "set-default", "site"]);
+    cmd.assert().success();
+    let saved = std::fs::read_to_string(&config_path)?;
+    assert!(saved.contains("port: 4321"), "{saved}");
+    for key in ["9000", "minify", "base_url"] {
+        assert!(!saved.contains(key), "`{key}` was saved:\n{saved}");
+    }
+
+    Ok(())
+}